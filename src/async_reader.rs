@@ -0,0 +1,259 @@
+//! Asynchronous reading of bags from `AsyncRead + AsyncSeek` sources
+use crate::reader::{check_record_len, decode_chunk, LoadedIndex};
+use crate::record_types::{Chunk, ChunkInfo, Connection};
+use crate::time::Time;
+use crate::{
+    Error, ErrorKind, IndexRecord, Message, MessageRecord, MessageRecordsIterator, Result,
+    VERSION_LEN,
+};
+use futures_core::Stream;
+use futures_util::stream::{self, TryStreamExt};
+use std::collections::HashMap;
+use std::io::{self, SeekFrom};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+/// Asynchronous reader of ROS bags stored in an `AsyncRead + AsyncSeek`
+/// source.
+///
+/// Similarly to [`BagReader`][crate::BagReader] only the bag header and
+/// the index section are kept in memory, while chunks are read on demand.
+/// Chunks are decompressed on the tokio blocking thread pool, so executor
+/// threads are not blocked by decompression. Methods which decompress
+/// chunks must be called from within a tokio runtime.
+///
+/// ```
+/// use futures_util::TryStreamExt;
+/// use rosbag::AsyncBagReader;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let rt = tokio::runtime::Builder::new_current_thread().build()?;
+/// # rt.block_on(async {
+/// // e.g. `tokio::fs::File`
+/// let file = std::io::Cursor::new(std::fs::read("dummy.bag")?);
+/// let mut reader = AsyncBagReader::new(file).await?;
+/// let mut messages = Box::pin(reader.messages());
+/// while let Some(msg) = messages.try_next().await? {
+///     println!("{} {}: {} bytes", msg.time, msg.conn.topic, msg.data().len());
+/// }
+/// # Ok(()) })
+/// # }
+/// ```
+pub struct AsyncBagReader<R> {
+    reader: R,
+    index: LoadedIndex,
+}
+
+/// Message positions in a decompressed chunk: `(conn_id, time, start, end)`.
+type MessageEntries = Vec<(u32, Time, usize, usize)>;
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncBagReader<R> {
+    /// Read bag header and index section from `reader`.
+    pub async fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0)).await?;
+        reader.seek(SeekFrom::Start(0)).await?;
+        let mut version = [0u8; VERSION_LEN as usize];
+        reader.read_exact(&mut version).await?;
+        LoadedIndex::check_version(&version)?;
+
+        let record = read_record(&mut reader, len, VERSION_LEN).await?;
+        let mut index = LoadedIndex::new(len, &record)?;
+        reader.seek(SeekFrom::Start(index.index_pos)).await?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        index.set_data(data)?;
+        Ok(Self { reader, index })
+    }
+
+    /// Read and decompress `Chunk` record which starts at `pos`.
+    ///
+    /// Positions of chunks are stored in `ChunkInfo` records, see
+    /// [`AsyncBagReader::chunk_infos`].
+    pub async fn read_chunk(&mut self, pos: u64) -> Result<Chunk<'static>> {
+        self.index.check_chunk_pos(pos)?;
+        let record = read_record(&mut self.reader, self.index.len, pos).await?;
+        let res = spawn_decode(record, |record| decode_chunk(&record)).await;
+        res.map_err(|e| e.at(pos))
+    }
+
+    /// Get stream of chunks in the order of their storage in the file.
+    pub fn chunks(&mut self) -> impl Stream<Item = Result<Chunk<'static>>> + '_ {
+        chunk_stream(&mut self.reader, &self.index, |record| {
+            decode_chunk(&record)
+        })
+    }
+
+    /// Get stream of messages in the order of their storage in the file.
+    ///
+    /// Unlike [`RosBag::messages`][crate::RosBag::messages] messages from
+    /// chunks with overlapping time ranges are not merged by their receive
+    /// time.
+    pub fn messages(&mut self) -> impl Stream<Item = Result<Message<'_>>> + '_ {
+        let index = &self.index;
+        let connections: HashMap<u32, Connection<'_>> =
+            index.connections().map(|conn| (conn.id, conn)).collect();
+        chunk_stream(&mut self.reader, index, index_messages)
+            .map_ok(move |(data, entries)| {
+                let messages = entries.into_iter().map(|(conn_id, time, start, end)| {
+                    let conn = connections.get(&conn_id).cloned().ok_or_else(|| {
+                        let err = Error::new(ErrorKind::InvalidRecord).in_field("conn");
+                        err.in_record("MessageData")
+                    })?;
+                    Ok(Message::from_shared(conn, time, data.clone(), start, end))
+                });
+                stream::iter(messages.collect::<Vec<_>>())
+            })
+            .try_flatten()
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R> AsyncBagReader<R> {
+    /// Get connection count in this rosbag file.
+    pub fn get_conn_count(&self) -> u32 {
+        self.index.conn_count
+    }
+
+    /// Get chunk count in this rosbag file.
+    pub fn get_chunk_count(&self) -> u32 {
+        self.index.chunk_count
+    }
+
+    /// Get stream of records in the index section.
+    ///
+    /// The index section is kept in memory, so the stream is always ready.
+    pub fn index_records(&self) -> impl Stream<Item = Result<IndexRecord<'_>>> + '_ {
+        stream::iter(self.index.index_records())
+    }
+
+    /// Get connection with the given ID.
+    pub fn connection(&self, id: u32) -> Option<Connection<'_>> {
+        self.index.connection(id)
+    }
+
+    /// Get iterator over all connections in the bag sorted by their IDs.
+    pub fn connections(&self) -> impl Iterator<Item = Connection<'_>> {
+        self.index.connections()
+    }
+
+    /// Get iterator over `ChunkInfo` records in the index section.
+    pub fn chunk_infos(&self) -> impl Iterator<Item = ChunkInfo<'_>> {
+        self.index.chunk_infos()
+    }
+
+    /// Get number of messages which arrived on connection with the given ID.
+    pub fn message_count(&self, conn_id: u32) -> u64 {
+        self.index.message_count(conn_id)
+    }
+}
+
+/// Read record which starts at `pos` including its length prefixes.
+async fn read_record<R>(reader: &mut R, len: u64, pos: u64) -> Result<Vec<u8>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let at = |e: io::Error| Error::from(e).at(pos);
+    reader.seek(SeekFrom::Start(pos)).await.map_err(at)?;
+    let mut buf = Vec::new();
+    // header and data
+    for _ in 0..2 {
+        let n = reader.read_u32_le().await.map_err(at)?;
+        buf.extend_from_slice(&n.to_le_bytes());
+        check_record_len(pos, buf.len(), n, len)?;
+        let start = buf.len();
+        buf.resize(start + n as usize, 0);
+        reader.read_exact(&mut buf[start..]).await.map_err(at)?;
+    }
+    Ok(buf)
+}
+
+/// Decode chunk `record` on the blocking thread pool.
+async fn spawn_decode<T, F>(record: Vec<u8>, decode: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(Vec<u8>) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || decode(record))
+        .await
+        .map_err(|e| Error::from(io::Error::new(io::ErrorKind::Other, e)))?
+}
+
+/// Get stream of chunks decoded by `decode` in the order of their storage.
+fn chunk_stream<'a, R, T, F>(
+    reader: &'a mut R,
+    index: &'a LoadedIndex,
+    decode: F,
+) -> impl Stream<Item = Result<T>> + 'a
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+    T: Send + 'static,
+    F: Fn(Vec<u8>) -> Result<T> + Copy + Send + 'static,
+{
+    let mut positions: Vec<u64> = index.chunk_infos().map(|info| info.chunk_pos).collect();
+    positions.sort_unstable();
+    positions.dedup();
+    let state = (reader, positions.into_iter());
+    stream::try_unfold(state, move |(reader, mut positions)| async move {
+        let pos = match positions.next() {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        index.check_chunk_pos(pos)?;
+        let record = read_record(reader, index.len, pos).await?;
+        let item = spawn_decode(record, decode).await.map_err(|e| e.at(pos))?;
+        Ok(Some((item, (reader, positions))))
+    })
+}
+
+/// Decompress chunk `record` and find positions of its messages.
+fn index_messages(record: Vec<u8>) -> Result<(Arc<Vec<u8>>, MessageEntries)> {
+    let data = decode_chunk(&record)?.data.into_shared();
+    let mut entries = Vec::new();
+    let mut records = MessageRecordsIterator::new(&data);
+    while let Some(record) = records.next() {
+        if let MessageRecord::MessageData(msg) = record? {
+            let end = records.cursor.pos() as usize;
+            entries.push((msg.conn_id, msg.time, end - msg.data.len(), end));
+        }
+    }
+    Ok((data, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{AsyncBagReader, RosBag};
+    use futures_util::TryStreamExt;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn messages() {
+        let data = write_bag(Compression::Lz4, 10, 4);
+        let bag = RosBag::from_bytes(&data).unwrap();
+        let expected: Vec<_> = bag
+            .messages()
+            .map(|msg| {
+                let msg = msg.unwrap();
+                (msg.conn.id, msg.time, msg.data().to_vec())
+            })
+            .collect();
+
+        let mut reader = AsyncBagReader::new(Cursor::new(&data)).await.unwrap();
+        assert_eq!(reader.connections().count(), 2);
+        let messages: Vec<_> = reader
+            .messages()
+            .map_ok(|msg| (msg.conn.id, msg.time, msg.data().to_vec()))
+            .try_collect()
+            .await
+            .unwrap();
+        // chunks do not overlap, so the storage order is sorted by time
+        assert_eq!(messages, expected);
+        let chunks: Vec<_> = reader.chunks().try_collect().await.unwrap();
+        assert_eq!(chunks.len(), 5);
+    }
+}
@@ -0,0 +1,248 @@
+//! In-memory table of connections and chunks built from the index section
+use crate::record_types::{ChunkInfo, Connection, OwnedConnection};
+use crate::{Error, IndexRecord, IndexRecordsIterator};
+use std::collections::BTreeMap;
+
+/// Connections and chunks parsed from the index section.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BagIndex {
+    /// Parsed `Connection` records by connection ID
+    pub(crate) connections: BTreeMap<u32, OwnedConnection>,
+    /// Sorted connection IDs by storage topic
+    pub(crate) topics: BTreeMap<String, Vec<u32>>,
+    /// Parsed `ChunkInfo` records together with their positions
+    pub(crate) chunk_infos: Vec<(u64, ChunkInfo<'static>)>,
+    /// Number of messages by connection ID summed over `ChunkInfo` records
+    pub(crate) msg_counts: BTreeMap<u32, u64>,
+    /// Position of the first record which can not be read, the following
+    /// records are not indexed
+    pub(crate) error_pos: Option<u64>,
+}
+
+impl BagIndex {
+    /// Index `records` up to the first record which can not be read.
+    pub(crate) fn build(mut records: IndexRecordsIterator<'_>) -> Self {
+        let mut index = Self::default();
+        loop {
+            let pos = records.offset + records.cursor.pos();
+            let record = match records.next() {
+                Some(Ok(record)) => record,
+                Some(Err(_)) => {
+                    index.error_pos = Some(pos);
+                    break;
+                }
+                None => break,
+            };
+            match record {
+                IndexRecord::Connection(conn) => {
+                    let (id, topic) = (conn.id, conn.storage_topic.to_string());
+                    if let Some(old) = index.connections.insert(id, conn.into_owned()) {
+                        let ids = index.topics.get_mut(&old.storage_topic);
+                        ids.expect("topic is present").retain(|&id| id != old.id);
+                    }
+                    index.topics.entry(topic).or_default().push(id);
+                }
+                IndexRecord::ChunkInfo(info) => {
+                    for entry in info.entries() {
+                        *index.msg_counts.entry(entry.conn_id).or_default() += entry.count as u64;
+                    }
+                    index.chunk_infos.push((pos, info.into_owned()));
+                }
+                IndexRecord::IndexData(_) => {}
+            }
+        }
+        index.topics.retain(|_, ids| !ids.is_empty());
+        for (topic, ids) in index.topics.iter_mut() {
+            ids.sort_unstable();
+            let tp = &index.connections[&ids[0]].tp;
+            if ids.iter().any(|id| &index.connections[id].tp != tp) {
+                log::warn!(
+                    "connections on topic {} have different message types",
+                    topic
+                );
+            }
+        }
+        index
+    }
+
+    /// Get error of the first record in the index section `records` which
+    /// can not be read.
+    pub(crate) fn error(&self, mut records: IndexRecordsIterator<'_>) -> Option<Error> {
+        let pos = self.error_pos?;
+        if let Err(err) = records.seek(pos) {
+            return Some(err.at(pos));
+        }
+        records.next()?.err()
+    }
+
+    /// Get iterator over `ChunkInfo` records in the order of their storage.
+    pub(crate) fn chunk_infos(&self) -> impl Iterator<Item = ChunkInfo<'_>> {
+        self.chunk_infos.iter().map(|(_, info)| info.as_borrowed())
+    }
+
+    pub(crate) fn connection(&self, id: u32) -> Option<Connection<'_>> {
+        self.connections
+            .get(&id)
+            .map(OwnedConnection::as_connection)
+    }
+
+    /// Get iterator over all connections sorted by their IDs.
+    pub(crate) fn connections(&self) -> impl Iterator<Item = Connection<'_>> {
+        self.connections
+            .values()
+            .map(OwnedConnection::as_connection)
+    }
+
+    /// Get iterator over connections which messages are stored on `topic`.
+    pub(crate) fn topic_connections<'a>(
+        &'a self,
+        topic: &str,
+    ) -> impl Iterator<Item = Connection<'a>> + 'a {
+        let ids = self.topics.get(topic).map(Vec::as_slice).unwrap_or(&[]);
+        ids.iter()
+            .map(move |id| self.connection(*id).expect("connection is present"))
+    }
+
+    /// Get information about topics sorted by topic name.
+    pub(crate) fn topics(&self) -> Vec<TopicInfo<'_>> {
+        let topics = self.topics.iter().map(|(topic, ids)| {
+            let connections: Vec<_> = self.topic_connections(topic).collect();
+            TopicInfo {
+                topic,
+                tp: connections[0].tp,
+                message_definition: connections[0].message_definition,
+                message_count: ids.iter().map(|id| self.message_count(*id)).sum(),
+                connections,
+            }
+        });
+        topics.collect()
+    }
+
+    pub(crate) fn message_count(&self, conn_id: u32) -> u64 {
+        self.msg_counts.get(&conn_id).copied().unwrap_or(0)
+    }
+}
+
+/// Information about a topic stored in the bag.
+#[derive(Debug, Clone)]
+pub struct TopicInfo<'a> {
+    /// Topic on which the messages are stored
+    pub topic: &'a str,
+    /// Message type of the first connection, see
+    /// [`TopicInfo::has_conflicting_types`]
+    pub tp: &'a str,
+    /// Full text of the message definition of the first connection
+    pub message_definition: &'a str,
+    /// Connections which recorded messages on this topic (e.g. one connection
+    /// per publishing node)
+    pub connections: Vec<Connection<'a>>,
+    /// Number of messages stored on this topic
+    pub message_count: u64,
+}
+
+impl<'a> TopicInfo<'a> {
+    /// Check if connections on this topic have different message types or
+    /// MD5 sums, in which case `tp` and `message_definition` describe only
+    /// some of the messages.
+    pub fn has_conflicting_types(&self) -> bool {
+        let first = &self.connections[0];
+        let same = |conn: &Connection<'_>| conn.tp == first.tp && conn.md5sum == first.md5sum;
+        !self.connections.iter().all(same)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::{Compression, MessageData};
+    use crate::test_utils::{connection, string_msg, write_bag};
+    use crate::verify::IssueKind;
+    use crate::{BagReader, BagWriter, ErrorKind, RosBag};
+    use std::io::Cursor;
+
+    #[test]
+    fn topics() {
+        let bag = RosBag::from_bytes(write_bag(Compression::None, 3, 4)).unwrap();
+        let topics = bag.topics();
+        let names: Vec<_> = topics.iter().map(|t| t.topic).collect();
+        assert_eq!(names, ["/a", "/b"]);
+        assert!(topics.iter().all(|t| t.message_count == 3));
+        assert_eq!(bag.connection(1).unwrap().topic, "/b");
+        assert!(bag.connection(2).is_none());
+
+        // two connections on the same topic
+        let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+        for id in [2, 0, 1] {
+            let mut conn = connection(id % 2);
+            conn.id = id;
+            writer.add_connection(&conn).unwrap();
+        }
+        let data = string_msg("x");
+        for conn_id in [0, 1, 2, 2] {
+            let msg = MessageData {
+                conn_id,
+                time: Default::default(),
+                data: &data,
+            };
+            writer.write_message(&msg).unwrap();
+        }
+        let bag = RosBag::from_bytes(writer.finish().unwrap().into_inner()).unwrap();
+        let topics = bag.topics();
+        assert_eq!(topics.len(), 2);
+        let ids: Vec<_> = topics[0].connections.iter().map(|c| c.id).collect();
+        assert_eq!(
+            (topics[0].topic, ids, topics[0].message_count),
+            ("/a", vec![0, 2], 3)
+        );
+        let ids: Vec<_> = bag.topic_connections("/b").map(|c| c.id).collect();
+        assert_eq!(ids, [1]);
+        assert_eq!(bag.topic_connections("/c").count(), 0);
+    }
+
+    #[test]
+    fn conflicting_types() {
+        let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_connection(&connection(0)).unwrap();
+        writer.add_connection(&connection(1)).unwrap();
+        let mut conn = connection(0);
+        conn.id = 2;
+        conn.tp = "std_msgs/Header";
+        writer.add_connection(&conn).unwrap();
+        let bag = RosBag::from_bytes(writer.finish().unwrap().into_inner()).unwrap();
+        let topics = bag.topics();
+        assert_eq!(topics[0].tp, "std_msgs/String");
+        assert!(topics[0].has_conflicting_types());
+        assert!(!topics[1].has_conflicting_types());
+    }
+
+    #[test]
+    fn invalid_index_record() {
+        let mut data = write_bag(Compression::None, 4, 4);
+        // change version of the last `ChunkInfo` record
+        let pos = data.windows(4).rposition(|w| w == b"ver=").unwrap();
+        data[pos + 4] = 2;
+
+        assert!(BagReader::new(Cursor::new(data.clone())).is_err());
+        let bag = RosBag::from_bytes(data).unwrap();
+        let err = bag.index_error().unwrap();
+        assert_eq!(err.kind(), &ErrorKind::UnsupportedVersion);
+        assert_eq!(bag.connections().count(), 2);
+        assert_eq!(bag.chunk_infos().count(), 1);
+        assert_eq!(bag.chunk_records().filter(|r| r.is_ok()).count(), 6);
+
+        assert!(bag.info().is_err());
+        let mut messages = bag.messages();
+        assert!(messages.next().unwrap().is_err());
+        assert!(messages.next().is_none());
+
+        let report = bag.verify();
+        assert_eq!(report.issues.len(), 2, "{}", report);
+        assert!(matches!(report.issues[0].kind, IssueKind::UnindexedChunk));
+        assert!(report.issues[1].pos < pos as u64);
+        match &report.issues[1].kind {
+            IssueKind::InvalidIndexRecord(err) => {
+                assert_eq!(err.kind(), &ErrorKind::UnsupportedVersion);
+            }
+            kind => panic!("unexpected issue: {}", kind),
+        }
+    }
+}
@@ -0,0 +1,566 @@
+//! Command-line tool for inspecting ROS bag files without ROS installation.
+use rosbag::record_types::Connection;
+use rosbag::value::{MessageDecoder, Value};
+use rosbag::{Hex, RosBag};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::{env, fmt, process};
+
+const USAGE: &str = "\
+Usage: rosbag <command> [options] <bag>
+
+Commands:
+    info [--yaml] <bag>             Print summary of the bag contents
+    topics <bag>                    List topics with message counts and types
+    echo [-n <count>] <bag> <topic> Print decoded messages stored on the topic
+    dump-connections <bag>          Print all connection records
+    check <bag>                     Verify index and decode all messages
+
+Options:
+    -h, --help                      Print this help
+    -V, --version                   Print version";
+
+type BoxResult<T> = Result<T, Box<dyn Error>>;
+
+/// Error in the command line arguments.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for UsageError {}
+
+fn usage_error<T>(msg: &str) -> BoxResult<T> {
+    Err(Box::new(UsageError(msg.to_string())))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let res = run(&args, &mut out);
+    // output written before an error is printed as well
+    let flushed = out.flush();
+    let code = match res.and_then(|ok| Ok(flushed.map(|_| ok)?)) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            if let Some(err) = err.downcast_ref::<io::Error>() {
+                if err.kind() == io::ErrorKind::BrokenPipe {
+                    process::exit(0);
+                }
+            }
+            eprintln!("error: {}", err);
+            let mut source = err.source();
+            while let Some(err) = source {
+                eprintln!("  caused by: {}", err);
+                source = err.source();
+            }
+            if err.is::<UsageError>() {
+                eprintln!("\n{}", USAGE);
+                2
+            } else {
+                1
+            }
+        }
+    };
+    process::exit(code);
+}
+
+/// Run command, returns `false` if the command found problems in the bag.
+fn run(args: &[String], out: &mut impl Write) -> BoxResult<bool> {
+    let (cmd, args) = match args.split_first() {
+        Some((cmd, args)) => (cmd.as_str(), args),
+        None => return usage_error("missing command"),
+    };
+    match cmd {
+        "-h" | "--help" | "help" => writeln!(out, "{}", USAGE)?,
+        "-V" | "--version" => writeln!(out, "rosbag {}", env!("CARGO_PKG_VERSION"))?,
+        "info" => {
+            let (flags, args) = parse_flags(args, &["--yaml"], &[])?;
+            let [path] = positional::<1>(&args)?;
+            let bag = RosBag::new(path)?;
+            let mut info = bag.info()?;
+            info.path = Some(path.to_string());
+            match flags.contains_key("--yaml") {
+                true => write!(out, "{}", info.to_yaml())?,
+                false => writeln!(out, "{}", info)?,
+            }
+        }
+        "topics" => {
+            let [path] = positional::<1>(args)?;
+            topics(out, &RosBag::new(path)?)?;
+        }
+        "echo" => {
+            let (flags, args) = parse_flags(args, &[], &["-n"])?;
+            let limit = match flags.get("-n") {
+                Some(n) => match n.parse::<usize>() {
+                    Ok(n) => n,
+                    Err(_) => return usage_error("invalid message count"),
+                },
+                None => usize::MAX,
+            };
+            let [path, topic] = positional::<2>(&args)?;
+            echo(out, &RosBag::new(path)?, topic, limit)?;
+        }
+        "dump-connections" => {
+            let [path] = positional::<1>(args)?;
+            for conn in RosBag::new(path)?.connections() {
+                dump_connection(out, &conn)?;
+            }
+        }
+        "check" => {
+            let [path] = positional::<1>(args)?;
+            return check(out, &RosBag::new(path)?);
+        }
+        _ => return usage_error(&format!("unknown command: {}", cmd)),
+    }
+    Ok(true)
+}
+
+/// Split arguments into flags and positional arguments.
+///
+/// `switches` do not take a value, while `options` take the next argument
+/// as their value.
+fn parse_flags<'a>(
+    args: &'a [String],
+    switches: &[&str],
+    options: &[&str],
+) -> BoxResult<(HashMap<&'a str, &'a str>, Vec<String>)> {
+    let mut flags = HashMap::new();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let arg = arg.as_str();
+        if switches.contains(&arg) {
+            flags.insert(arg, "");
+        } else if options.contains(&arg) {
+            match iter.next() {
+                Some(val) => flags.insert(arg, val.as_str()),
+                None => return usage_error(&format!("missing value for {}", arg)),
+            };
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            return usage_error(&format!("unknown option: {}", arg));
+        } else {
+            positional.push(arg.to_string());
+        }
+    }
+    Ok((flags, positional))
+}
+
+fn positional<const N: usize>(args: &[String]) -> BoxResult<[&str; N]> {
+    if let Some(arg) = args.iter().find(|a| a.starts_with('-') && a.len() > 1) {
+        return usage_error(&format!("unknown option: {}", arg));
+    }
+    let mut res = [""; N];
+    if args.len() != N {
+        return usage_error("wrong number of arguments");
+    }
+    for (r, a) in res.iter_mut().zip(args) {
+        *r = a.as_str();
+    }
+    Ok(res)
+}
+
+fn topics(out: &mut impl Write, bag: &RosBag) -> BoxResult<()> {
+    let topics = bag.topics();
+    let topic_w = topics.iter().map(|t| t.topic.len()).max().unwrap_or(0);
+    let count_w = topics.iter().map(|t| t.message_count.to_string().len());
+    let count_w = count_w.max().unwrap_or(0);
+    for topic in topics {
+        let msgs = if topic.message_count == 1 {
+            "msg "
+        } else {
+            "msgs"
+        };
+        writeln!(
+            out,
+            "{:<tw$}  {:>cw$} {}  {}",
+            topic.topic,
+            topic.message_count,
+            msgs,
+            topic.tp,
+            tw = topic_w,
+            cw = count_w,
+        )?;
+    }
+    Ok(())
+}
+
+fn echo(out: &mut impl Write, bag: &RosBag, topic: &str, limit: usize) -> BoxResult<()> {
+    let mut decoders = HashMap::new();
+    for conn in bag.topic_connections(topic) {
+        decoders.insert(conn.id, MessageDecoder::from_connection(&conn)?);
+    }
+    if decoders.is_empty() {
+        return Err(format!("topic not found in the bag: {}", topic).into());
+    }
+    for msg in bag.messages().topics([topic]).take(limit) {
+        let msg = msg?;
+        let value = decoders[&msg.conn.id].decode(msg.data())?;
+        let mut s = String::new();
+        write_nested(&mut s, 0, &value);
+        writeln!(out, "{}---", s)?;
+    }
+    Ok(())
+}
+
+/// Write value in the YAML-like format used by `rostopic echo`.
+fn write_nested(s: &mut String, indent: usize, value: &Value) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Message(fields) => {
+            for (name, value) in fields {
+                match inline(value) {
+                    Some(v) => s.push_str(&format!("{}{}: {}\n", pad, name, v)),
+                    None => {
+                        s.push_str(&format!("{}{}:\n", pad, name));
+                        write_nested(s, indent + 2, value);
+                    }
+                }
+            }
+        }
+        Value::Time(t) => {
+            let (secs, nsecs) = (t.sec(), t.nsec());
+            s.push_str(&format!("{}secs: {}\n{}nsecs: {}\n", pad, secs, pad, nsecs));
+        }
+        Value::Duration(d) => {
+            let (secs, nsecs) = (d.sec(), d.nsec());
+            s.push_str(&format!("{}secs: {}\n{}nsecs: {}\n", pad, secs, pad, nsecs));
+        }
+        Value::Array(items) => {
+            for item in items {
+                match inline(item) {
+                    Some(v) => s.push_str(&format!("{}- {}\n", pad, v)),
+                    None => {
+                        s.push_str(&format!("{}-\n", pad));
+                        write_nested(s, indent + 2, item);
+                    }
+                }
+            }
+        }
+        value => {
+            let v = inline(value).expect("scalar values are inlined");
+            s.push_str(&format!("{}{}\n", pad, v));
+        }
+    }
+}
+
+/// Format value on a single line if it does not contain nested messages.
+fn inline(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Bool(true) => "True".to_string(),
+        Value::Bool(false) => "False".to_string(),
+        Value::I8(v) => v.to_string(),
+        Value::U8(v) => v.to_string(),
+        Value::I16(v) => v.to_string(),
+        Value::U16(v) => v.to_string(),
+        Value::I32(v) => v.to_string(),
+        Value::U32(v) => v.to_string(),
+        Value::I64(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::F32(v) => format!("{:?}", v),
+        Value::F64(v) => format!("{:?}", v),
+        Value::String(v) => yaml_quote(v),
+        Value::Bytes(v) => {
+            let items = v.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Array(items) => {
+            let items = items.iter().map(|item| match item {
+                Value::Array(_) => None,
+                item => inline(item),
+            });
+            let items = items.collect::<Option<Vec<_>>>()?;
+            format!("[{}]", items.join(", "))
+        }
+        Value::Time(_) | Value::Duration(_) | Value::Message(_) => return None,
+    })
+}
+
+/// Quote string as a double-quoted YAML scalar.
+fn yaml_quote(v: &str) -> String {
+    let mut s = String::with_capacity(v.len() + 2);
+    s.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if c.is_control() => s.push_str(&format!("\\u{:04x}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    s
+}
+
+fn dump_connection(out: &mut impl Write, conn: &Connection<'_>) -> io::Result<()> {
+    writeln!(out, "connection {}:", conn.id)?;
+    writeln!(out, "  storage_topic: {}", conn.storage_topic)?;
+    writeln!(out, "  topic: {}", conn.topic)?;
+    writeln!(out, "  type: {}", conn.tp)?;
+    writeln!(out, "  md5sum: {}", Hex(&conn.md5sum))?;
+    writeln!(out, "  callerid: {}", conn.caller_id)?;
+    writeln!(out, "  latching: {}", conn.latching)?;
+    writeln!(out, "  message_definition: |")?;
+    for line in conn.message_definition.lines() {
+        writeln!(out, "    {}", line)?;
+    }
+    Ok(())
+}
+
+/// Verify bag structure, check MD5 sums of all connections and decode every
+/// message.
+fn check(out: &mut impl Write, bag: &RosBag) -> BoxResult<bool> {
+    let report = bag.verify();
+    let mut ok = report.is_ok();
+    writeln!(out, "{}", report)?;
+
+    for mismatch in bag.check_md5sums() {
+        ok = false;
+        let conn = &mismatch.conn;
+        let computed = match &mismatch.computed {
+            Ok(sum) => Hex(sum).to_string(),
+            Err(err) => err.to_string(),
+        };
+        writeln!(
+            out,
+            "connection {} on {} ({}): MD5 sum mismatch: stored {}, computed {}",
+            conn.id,
+            conn.storage_topic,
+            conn.tp,
+            Hex(&conn.md5sum),
+            computed,
+        )?;
+    }
+
+    let mut decoders = HashMap::new();
+    for conn in bag.connections() {
+        match MessageDecoder::from_connection(&conn) {
+            Ok(decoder) => {
+                decoders.insert(conn.id, decoder);
+            }
+            Err(err) => {
+                ok = false;
+                writeln!(
+                    out,
+                    "connection {} on {}: {}",
+                    conn.id, conn.storage_topic, err
+                )?;
+            }
+        }
+    }
+
+    let (mut count, mut failed) = (0u64, 0u64);
+    for msg in bag.messages() {
+        count += 1;
+        let res = msg.and_then(|msg| match decoders.get(&msg.conn.id) {
+            Some(decoder) => decoder.decode(msg.data()).map(drop),
+            None => Ok(()),
+        });
+        if let Err(err) = res {
+            failed += 1;
+            writeln!(out, "message {}: {}", count, err)?;
+        }
+    }
+    ok &= failed == 0;
+    writeln!(out, "checked {} messages, {} failed", count, failed)?;
+    writeln!(out, "{}", if ok { "OK" } else { "FAILED" })?;
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_flags, positional, run, UsageError};
+    use rosbag::record_types::{Connection, MessageData};
+    use rosbag::time::Time;
+    use rosbag::BagWriter;
+    use std::path::PathBuf;
+    use std::{env, fs, process};
+
+    const DEFINITION: &str = "\
+string data
+uint8[] bytes
+time stamp
+Point[] points
+================================================================================
+MSG: test_msgs/Point
+int32 x
+int32 y
+";
+
+    /// Bag file removed on drop.
+    struct TempBag(PathBuf);
+
+    impl Drop for TempBag {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn write_bag(name: &str) -> TempBag {
+        let file_name = format!("rosbag-cli-{}-{}.bag", process::id(), name);
+        let bag = TempBag(env::temp_dir().join(file_name));
+        let mut conn = Connection {
+            id: 0,
+            storage_topic: "/test",
+            topic: "/test",
+            tp: "test_msgs/Test",
+            md5sum: [0; 16],
+            message_definition: DEFINITION,
+            caller_id: "",
+            latching: false,
+        };
+        conn.md5sum = conn.compute_md5sum().unwrap();
+        let mut writer = BagWriter::create(&bag.0).unwrap();
+        writer.add_connection(&conn).unwrap();
+        for i in 0..3u8 {
+            let mut data = Vec::new();
+            let text = format!("say \"{}\"\n", i);
+            data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            data.extend_from_slice(text.as_bytes());
+            data.extend_from_slice(&[2, 0, 0, 0, i, 255]);
+            data.extend_from_slice(&[i, 0, 0, 0, 5, 0, 0, 0]);
+            data.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0, 254, 255, 255, 255]);
+            let msg = MessageData {
+                conn_id: 0,
+                time: Time::new(i as u32, 0),
+                data: &data,
+            };
+            writer.write_message(&msg).unwrap();
+        }
+        writer.finish().unwrap();
+        bag
+    }
+
+    /// Run command, returns its result and output.
+    fn run_args(cmd: &[&str]) -> (Result<bool, String>, String) {
+        let mut out = Vec::new();
+        let res = run(&args(cmd), &mut out).map_err(|e| e.to_string());
+        (res, String::from_utf8(out).unwrap())
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn arg_parsing() {
+        let all = args(&["-n", "5", "a.bag", "--yaml", "/topic"]);
+        let (flags, rest) = parse_flags(&all, &["--yaml"], &["-n"]).unwrap();
+        assert_eq!(flags.get("-n"), Some(&"5"));
+        assert_eq!(flags.get("--yaml"), Some(&""));
+        assert_eq!(rest, ["a.bag", "/topic"]);
+
+        let err = parse_flags(&args(&["a.bag", "-n"]), &[], &["-n"]).unwrap_err();
+        assert_eq!(err.to_string(), "missing value for -n");
+        let err = parse_flags(&args(&["-x"]), &[], &[]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown option: -x");
+        // `-` is a positional argument, e.g. stdin
+        let (_, rest) = parse_flags(&args(&["-"]), &[], &[]).unwrap();
+        assert_eq!(rest, ["-"]);
+
+        let two = args(&["a", "b"]);
+        assert_eq!(positional::<2>(&two).unwrap(), ["a", "b"]);
+        let err = positional::<1>(&two).unwrap_err();
+        assert_eq!(err.to_string(), "wrong number of arguments");
+        let err = positional::<1>(&args(&["--yaml"])).unwrap_err();
+        assert_eq!(err.to_string(), "unknown option: --yaml");
+
+        for (cmd, msg) in [
+            (&[][..], "missing command"),
+            (&["foo"][..], "unknown command: foo"),
+            (&["info"][..], "wrong number of arguments"),
+            (
+                &["echo", "-n", "x", "a.bag", "/t"][..],
+                "invalid message count",
+            ),
+            (
+                &["reindex", "a.bag", "b.bag"][..],
+                "unknown command: reindex",
+            ),
+        ] {
+            let mut out = Vec::new();
+            let err = run(&args(cmd), &mut out).unwrap_err();
+            assert!(err.is::<UsageError>());
+            assert_eq!(err.to_string(), msg);
+            assert!(out.is_empty());
+        }
+    }
+
+    #[test]
+    fn echo() {
+        let bag = write_bag("echo");
+        let path = bag.0.to_str().unwrap();
+        let (res, out) = run_args(&["echo", "-n", "2", path, "/test"]);
+        assert_eq!(res, Ok(true));
+        let expected = "\
+data: \"say \\\"0\\\"\\n\"
+bytes: [0, 255]
+stamp:
+  secs: 0
+  nsecs: 5
+points:
+  -
+    x: 1
+    y: -2
+---
+data: \"say \\\"1\\\"\\n\"
+bytes: [1, 255]
+stamp:
+  secs: 1
+  nsecs: 5
+points:
+  -
+    x: 1
+    y: -2
+---
+";
+        assert_eq!(out, expected);
+
+        let (res, _) = run_args(&["echo", path, "/missing"]);
+        assert_eq!(res, Err("topic not found in the bag: /missing".to_string()));
+    }
+
+    #[test]
+    fn info() {
+        let bag = write_bag("info");
+        let path = bag.0.to_str().unwrap();
+        let (res, out) = run_args(&["info", path]);
+        assert_eq!(res, Ok(true));
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some(&*format!("path:        {}", path)));
+        let expected = [
+            "version:     2.0",
+            "duration:    2.0s",
+            "start:       Jan 01 1970 00:00:00.00 (0.00)",
+            "end:         Jan 01 1970 00:00:02.00 (2.00)",
+            "size:        5.1 KB",
+            "messages:    3",
+            "compression: none [1/1 chunks]",
+            "types:       test_msgs/Test [1b4628ce55fa24cb9b91b6d9b6edb775]",
+            "topics:      /test   3 msgs @ 1.0 Hz : test_msgs/Test",
+        ];
+        assert_eq!(lines.collect::<Vec<_>>(), expected);
+
+        let (res, out) = run_args(&["info", "--yaml", path]);
+        assert_eq!(res, Ok(true));
+        assert!(out.contains("\nmessages: 3\n"), "{}", out);
+
+        let (res, out) = run_args(&["check", path]);
+        assert_eq!(res, Ok(true));
+        assert!(
+            out.ends_with("checked 3 messages, 0 failed\nOK\n"),
+            "{}",
+            out
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! LRU cache of decompressed chunks
+use crate::record_types::{Chunk, ChunkBytes, Compression, RawChunk};
+use crate::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Decompressed chunk data bounded by the total size in bytes.
+#[derive(Debug, Default)]
+pub(crate) struct ChunkCache {
+    /// Maximum total size of cached data in bytes
+    capacity: usize,
+    /// Total size of cached data in bytes
+    size: usize,
+    /// Decompressed data and last access tick by chunk position
+    entries: HashMap<u64, (Arc<Vec<u8>>, u64)>,
+    /// Chunk positions by their last access tick
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl ChunkCache {
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Set maximum total size of cached data evicting least recently used
+    /// chunks if necessary.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(0);
+    }
+
+    pub(crate) fn contains(&self, pos: u64) -> bool {
+        self.entries.contains_key(&pos)
+    }
+
+    pub(crate) fn get(&mut self, pos: u64) -> Option<Arc<Vec<u8>>> {
+        let (data, tick) = self.entries.get_mut(&pos)?;
+        self.lru.remove(tick);
+        self.tick += 1;
+        *tick = self.tick;
+        self.lru.insert(self.tick, pos);
+        Some(data.clone())
+    }
+
+    pub(crate) fn insert(&mut self, pos: u64, data: Arc<Vec<u8>>) {
+        if data.len() > self.capacity || self.contains(pos) {
+            return;
+        }
+        self.evict(data.len());
+        self.tick += 1;
+        self.size += data.len();
+        self.lru.insert(self.tick, pos);
+        self.entries.insert(pos, (data, self.tick));
+    }
+
+    /// Evict least recently used chunks until `additional` bytes fit into
+    /// the cache.
+    fn evict(&mut self, additional: usize) {
+        while self.size + additional > self.capacity {
+            let (tick, pos) = match self.lru.iter().next() {
+                Some((&tick, &pos)) => (tick, pos),
+                None => break,
+            };
+            self.lru.remove(&tick);
+            let (data, _) = self.entries.remove(&pos).expect("entry is present");
+            self.size -= data.len();
+        }
+    }
+}
+
+/// Lock the cache ignoring poisoning, since the cache is left in
+/// a consistent state by all of its methods.
+pub(crate) fn lock(cache: &Mutex<ChunkCache>) -> std::sync::MutexGuard<'_, ChunkCache> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Decompress chunk stored at `pos` reusing data from `cache`.
+///
+/// Uncompressed chunks are borrowed from the bag and are not cached.
+pub(crate) fn decompress<'a>(
+    cache: &Mutex<ChunkCache>,
+    pos: u64,
+    raw: RawChunk<'a>,
+) -> Result<Chunk<'a>> {
+    if raw.compression == Compression::None || lock(cache).capacity() == 0 {
+        return raw.decompress();
+    }
+    if let Some(data) = lock(cache).get(pos) {
+        return Ok(Chunk {
+            compression: raw.compression,
+            data: ChunkBytes::Shared(data),
+        });
+    }
+    // decompress without holding the lock
+    let compression = raw.compression;
+    let data = raw.decompress()?.data.into_shared();
+    lock(cache).insert(pos, data.clone());
+    Ok(Chunk {
+        compression,
+        data: ChunkBytes::Shared(data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkCache;
+    use std::sync::Arc;
+
+    #[test]
+    fn eviction() {
+        let mut cache = ChunkCache::default();
+        // disabled cache
+        cache.insert(0, Arc::new(vec![0; 4]));
+        assert!(!cache.contains(0));
+
+        cache.set_capacity(10);
+        cache.insert(0, Arc::new(vec![0; 4]));
+        cache.insert(1, Arc::new(vec![1; 4]));
+        // chunk 1 becomes the least recently used one
+        assert_eq!(cache.get(0).unwrap()[..], [0; 4]);
+        cache.insert(2, Arc::new(vec![2; 4]));
+        assert!(cache.contains(0) && !cache.contains(1) && cache.contains(2));
+        assert!(cache.get(1).is_none());
+
+        // chunks larger than the capacity are not cached
+        cache.insert(3, Arc::new(vec![3; 11]));
+        assert!(!cache.contains(3) && cache.contains(0) && cache.contains(2));
+
+        cache.set_capacity(5);
+        assert!(!cache.contains(0) && cache.contains(2));
+        cache.set_capacity(0);
+        assert!(!cache.contains(2));
+        assert_eq!(cache.size, 0);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::record_types::{Chunk, IndexData};
-use crate::{record::Record, Cursor, Error, Result};
+use crate::cache::{self, ChunkCache};
+use crate::record::{read_op, Record};
+use crate::record_types::{Chunk, IndexData, RawChunk, RecordGen};
+use crate::{Cursor, Error, ErrorKind, Result};
+use std::sync::Mutex;
 
 /// Record types which can be stored in the chunk section.
 #[derive(Debug, Clone)]
@@ -11,9 +14,15 @@ pub enum ChunkRecord<'a> {
 }
 
 /// Iterator over records stored in the chunk section of a rosbag file.
+///
+/// Chunks which data can not be decompressed are reported as errors and
+/// skipped, so the iteration continues with the following record. If
+/// a record can not be read at all, e.g. because fewer than 4 bytes are left
+/// in the chunk section, its error is yielded once and the iteration stops.
 pub struct ChunkRecordsIterator<'a> {
     pub(crate) cursor: Cursor<'a>,
     pub(crate) offset: u64,
+    pub(crate) cache: Option<&'a Mutex<ChunkCache>>,
 }
 
 impl<'a> ChunkRecordsIterator<'a> {
@@ -26,7 +35,7 @@ impl<'a> ChunkRecordsIterator<'a> {
     /// data).
     pub fn seek(&mut self, pos: u64) -> Result<()> {
         if pos < self.offset {
-            return Err(Error::OutOfBounds);
+            return Err(Error::new(ErrorKind::OutOfBounds));
         }
         Ok(self.cursor.seek(pos - self.offset)?)
     }
@@ -39,12 +48,112 @@ impl<'a> Iterator for ChunkRecordsIterator<'a> {
         if self.cursor.left() == 0 {
             return None;
         }
-        let res = match Record::next_record(&mut self.cursor) {
+        let pos = self.offset + self.cursor.pos();
+        let res = match self.cache {
+            Some(cache) => read_cached(&mut self.cursor, cache, pos),
+            None => Record::next_record(&mut self.cursor),
+        };
+        if res.is_err() && self.cursor.pos() + self.offset == pos {
+            // the record can not be skipped, stop on the next iteration
+            let end = self.cursor.len();
+            self.cursor.seek(end).expect("end is in bounds");
+        }
+        let res = match res {
             Ok(Record::Chunk(v)) => Ok(ChunkRecord::Chunk(v)),
             Ok(Record::IndexData(v)) => Ok(ChunkRecord::IndexData(v)),
-            Ok(v) => Err(Error::UnexpectedChunkSectionRecord(v.get_type())),
+            Ok(v) => Err(Error::new(ErrorKind::UnexpectedChunkSectionRecord(
+                v.get_type(),
+            ))),
             Err(e) => Err(e),
         };
-        Some(res)
+        Some(res.map_err(|e| e.at(pos)))
+    }
+}
+
+/// Same as [`Record::next_record`], but decompresses chunks using `cache`.
+fn read_cached<'a>(c: &mut Cursor<'a>, cache: &Mutex<ChunkCache>, pos: u64) -> Result<Record<'a>> {
+    let mut peek = c.clone();
+    let header = peek.next_chunk()?;
+    if read_op(header)? != Some(Chunk::OP) {
+        return Record::next_record(c);
+    }
+    let raw = RawChunk::read_with_header(header, &mut peek)?;
+    // skip the record even if its data can not be decompressed
+    *c = peek;
+    Ok(Record::Chunk(cache::decompress(cache, pos, raw)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkRecordsIterator;
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{ChunkRecord, Cursor, ErrorKind, RosBag, SharedBag};
+
+    /// Bag with two bz2 chunks, the first of which is corrupted.
+    fn corrupted_bag() -> RosBag<Vec<u8>> {
+        let mut data = write_bag(Compression::Bzip2, 4, 4);
+        let pos = data.windows(3).position(|w| w == b"BZh").unwrap();
+        // overwrite magic of the first compressed block
+        data[pos + 4..pos + 10].fill(0);
+        RosBag::from_bytes(data).unwrap()
+    }
+
+    fn check_records<I: Iterator<Item = crate::Result<ChunkRecord<'static>>>>(records: I) {
+        let kinds: Vec<_> = records
+            .map(|r| match r {
+                Ok(ChunkRecord::Chunk(_)) => "Chunk",
+                Ok(ChunkRecord::IndexData(_)) => "IndexData",
+                Err(e) => match e.kind() {
+                    ErrorKind::Decompression(Compression::Bzip2) => "error",
+                    kind => panic!("unexpected error: {:?}", kind),
+                },
+            })
+            .take(10)
+            .collect();
+        let expected = [
+            "error",
+            "IndexData",
+            "IndexData",
+            "Chunk",
+            "IndexData",
+            "IndexData",
+        ];
+        assert_eq!(kinds, expected);
+    }
+
+    #[test]
+    fn corrupted_chunk_is_skipped() {
+        let bag = corrupted_bag();
+        check_records(bag.chunk_records().map(|r| r.map(owned)));
+        bag.set_chunk_cache_capacity(1 << 20);
+        check_records(bag.chunk_records().map(|r| r.map(owned)));
+        check_records(SharedBag::new(bag).chunk_records());
+    }
+
+    fn owned(record: ChunkRecord<'_>) -> ChunkRecord<'static> {
+        match record {
+            ChunkRecord::Chunk(chunk) => ChunkRecord::Chunk(chunk.into_owned()),
+            ChunkRecord::IndexData(index) => ChunkRecord::IndexData(index.into_owned()),
+        }
+    }
+
+    #[test]
+    fn unreadable_record_stops_iteration() {
+        let bag = RosBag::from_bytes(write_bag(Compression::Lz4, 4, 4)).unwrap();
+        let mut data = bag.bytes()[bag.start_pos..bag.index_pos].to_vec();
+        let end = (bag.start_pos + data.len()) as u64;
+        data.extend_from_slice(&[1, 0, 0]);
+        let records = ChunkRecordsIterator {
+            cursor: Cursor::new(&data),
+            offset: bag.start_pos as u64,
+            cache: None,
+        };
+        let records: Vec<_> = records.take(10).collect();
+        assert_eq!(records.len(), 7);
+        assert!(records[..6].iter().all(|r| r.is_ok()));
+        let err = records[6].as_ref().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::OutOfBounds);
+        assert_eq!(err.offset(), Some(end));
     }
 }
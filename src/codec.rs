@@ -0,0 +1,263 @@
+//! Pluggable chunk compression codecs.
+//!
+//! Besides the built-in `bz2` and `lz4` compressions, chunks can be
+//! compressed by any type implementing the [`Codec`] trait. Codecs are
+//! registered for the whole process with [`register`] and get selected by
+//! the `compression` field of chunk headers while reading, while
+//! [`BagWriter::set_compression`][crate::BagWriter::set_compression] selects
+//! them for writing. With the `zstd` feature the [`Zstd`] codec is available
+//! under the `zstd` name without registration.
+//!
+//! # Example
+//! ```
+//! use rosbag::codec::{self, Codec};
+//! use rosbag::record_types::{Connection, MessageData};
+//! use rosbag::{BagWriter, MessageRecord, RosBag};
+//! use std::io;
+//!
+//! /// Codec which stores data reversed.
+//! struct Reversed;
+//!
+//! impl Codec for Reversed {
+//!     fn name(&self) -> &'static str {
+//!         "reversed"
+//!     }
+//!
+//!     fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+//!         Ok(data.iter().rev().copied().collect())
+//!     }
+//!
+//!     fn decompress(&self, data: &[u8], _size: usize) -> io::Result<Vec<u8>> {
+//!         Ok(data.iter().rev().copied().collect())
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let compression = codec::register(Reversed);
+//!
+//! let mut writer = BagWriter::new(io::Cursor::new(Vec::new()))?;
+//! writer.set_compression(compression);
+//! # let conn = Connection {
+//! #     id: 0,
+//! #     storage_topic: "/chatter",
+//! #     topic: "/chatter",
+//! #     tp: "std_msgs/String",
+//! #     md5sum: [0; 16],
+//! #     message_definition: "string data\n",
+//! #     caller_id: "",
+//! #     latching: false,
+//! # };
+//! # let msg = MessageData { conn_id: 0, time: Default::default(), data: b"\x02\x00\x00\x00hi" };
+//! writer.add_connection(&conn)?;
+//! writer.write_message(&msg)?;
+//! let data = writer.finish()?.into_inner();
+//!
+//! let bag = RosBag::from_bytes(data)?;
+//! let msg = bag.messages().next().unwrap()?;
+//! assert_eq!(msg.data(), b"\x02\x00\x00\x00hi");
+//! # Ok(()) }
+//! ```
+use crate::record_types::Compression;
+use std::io;
+use std::ptr;
+use std::sync::{Once, PoisonError, RwLock};
+
+/// Compression codec used for chunk data.
+pub trait Codec: Send + Sync {
+    /// Value of the `compression` field in headers of chunks compressed by
+    /// the codec, e.g. `"zstd"`.
+    fn name(&self) -> &'static str;
+
+    /// Compress chunk data.
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decompress chunk data.
+    ///
+    /// `size` is the size of decompressed data declared in the chunk header.
+    /// It can be used to preallocate the output buffer, but it is not
+    /// validated, so corrupted bags may contain arbitrary values.
+    fn decompress(&self, data: &[u8], size: usize) -> io::Result<Vec<u8>>;
+}
+
+type Registry = RwLock<Vec<&'static dyn Codec>>;
+
+/// Get registered codecs, the registry gets allocated on the first access.
+fn registry() -> &'static Registry {
+    static INIT: Once = Once::new();
+    static mut REGISTRY: Option<Registry> = None;
+    // SAFETY: `REGISTRY` is written only inside of `call_once`, which
+    // synchronizes the write with all reads following it
+    unsafe {
+        INIT.call_once(|| REGISTRY = Some(RwLock::new(Vec::new())));
+        (*ptr::addr_of!(REGISTRY))
+            .as_ref()
+            .expect("registry is initialized")
+    }
+}
+
+/// Get capacity to preallocate for decompressing `len` bytes of data with
+/// declared decompressed `size`.
+///
+/// Declared sizes are not validated, so the capacity is limited by
+/// a compression ratio rarely exceeded in practice.
+pub(crate) fn preallocation(size: usize, len: usize) -> usize {
+    size.min(len.saturating_mul(16))
+}
+
+/// Register `codec` for reading and writing chunks.
+///
+/// Returns compression which can be passed to
+/// [`BagWriter::set_compression`][crate::BagWriter::set_compression].
+/// Registered codecs are never dropped. Codec registered with the same name
+/// as an already registered one replaces it for reading.
+///
+/// # Panics
+/// If name of the codec is one of the built-in `none`, `bz2` or `lz4`.
+pub fn register<C: Codec + 'static>(codec: C) -> Compression {
+    let name = codec.name();
+    assert!(
+        !matches!(name, "none" | "bz2" | "lz4"),
+        "built-in compression can not be replaced: {}",
+        name,
+    );
+    let codec: &'static dyn Codec = Box::leak(Box::new(codec));
+    let mut codecs = registry().write().unwrap_or_else(PoisonError::into_inner);
+    codecs.retain(|c| c.name() != name);
+    codecs.push(codec);
+    Compression::Other(codec)
+}
+
+/// Find codec by its name among registered and feature-gated codecs.
+pub(crate) fn find(name: &str) -> Option<&'static dyn Codec> {
+    let codecs = registry().read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&codec) = codecs.iter().find(|c| c.name() == name) {
+        return Some(codec);
+    }
+    #[cfg(feature = "zstd")]
+    if name == ZSTD.name() {
+        return Some(&ZSTD);
+    }
+    None
+}
+
+#[cfg(feature = "zstd")]
+static ZSTD: Zstd = Zstd::new(0);
+
+/// Zstandard codec available under the `zstd` name.
+///
+/// The compression level affects only writing, to write chunks with a
+/// non-default level pass the result of registering `Zstd::new(level)`
+/// with [`register`] to
+/// [`BagWriter::set_compression`][crate::BagWriter::set_compression].
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy)]
+pub struct Zstd {
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+    /// Create codec with the provided compression level, `0` selects the
+    /// default level of the zstd library.
+    pub const fn new(level: i32) -> Self {
+        Self { level }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    fn name(&self) -> &'static str {
+        "zstd"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::bulk::compress(data, self.level)
+    }
+
+    fn decompress(&self, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        // frames written by other tools may not store the content size
+        let mut decompressed = Vec::with_capacity(preallocation(size, data.len()));
+        zstd::stream::copy_decode(data, &mut decompressed)?;
+        Ok(decompressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, register, Codec};
+    use std::io;
+
+    /// Codec which prepends `tag` to the data.
+    struct Tagged {
+        name: &'static str,
+        tag: u8,
+    }
+
+    impl Codec for Tagged {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+            Ok([&[self.tag], data].concat())
+        }
+
+        fn decompress(&self, data: &[u8], _size: usize) -> io::Result<Vec<u8>> {
+            Ok(data[1..].to_vec())
+        }
+    }
+
+    #[test]
+    fn register_replaces() {
+        let name = "tagged-replace";
+        assert!(find(name).is_none());
+        register(Tagged { name, tag: 1 });
+        assert_eq!(find(name).unwrap().compress(b"x").unwrap(), b"\x01x");
+        register(Tagged { name, tag: 2 });
+        assert_eq!(find(name).unwrap().compress(b"x").unwrap(), b"\x02x");
+    }
+
+    #[test]
+    #[should_panic(expected = "built-in compression can not be replaced: lz4")]
+    fn register_builtin() {
+        register(Tagged {
+            name: "lz4",
+            tag: 0,
+        });
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        use crate::record_types::Compression;
+        use crate::test_utils::write_bag;
+        use crate::{ChunkRecord, RosBag};
+
+        let bag = RosBag::from_bytes(write_bag(Compression::None, 10, 4)).unwrap();
+        let zstd = Compression::Other(find("zstd").unwrap());
+        let zstd_bag = RosBag::from_bytes(write_bag(zstd, 10, 4)).unwrap();
+        for record in zstd_bag.chunk_records() {
+            if let ChunkRecord::Chunk(chunk) = record.unwrap() {
+                assert_eq!(chunk.compression, zstd);
+            }
+        }
+        let expected: Vec<_> = bag.messages().map(|m| m.unwrap().data().to_vec()).collect();
+        let actual: Vec<_> = zstd_bag
+            .messages()
+            .map(|m| m.unwrap().data().to_vec())
+            .collect();
+        assert_eq!(actual.len(), 20);
+        assert_eq!(actual, expected);
+
+        // declared size is not trusted for preallocation
+        let data = super::ZSTD.compress(b"data").unwrap();
+        assert_eq!(super::ZSTD.decompress(&data, usize::MAX).unwrap(), b"data");
+    }
+}
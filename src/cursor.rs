@@ -1,5 +1,7 @@
+use crate::time::{Duration, Time};
 use byteorder::{ByteOrder, LE};
 
+#[derive(Clone)]
 pub(crate) struct Cursor<'a> {
     data: &'a [u8],
     pos: u64,
@@ -57,9 +59,15 @@ impl<'a> Cursor<'a> {
     }
     */
 
-    pub fn next_time(&mut self) -> Result<u64, OutOfBounds> {
-        let s = self.next_u32()? as u64;
-        let ns = self.next_u32()? as u64;
-        Ok(1_000_000_000 * s + ns)
+    pub fn next_time(&mut self) -> Result<Time, OutOfBounds> {
+        let sec = self.next_u32()?;
+        let nsec = self.next_u32()?;
+        Ok(Time::new(sec, nsec))
+    }
+
+    pub fn next_duration(&mut self) -> Result<Duration, OutOfBounds> {
+        let sec = LE::read_i32(self.next_bytes(4)?);
+        let nsec = LE::read_i32(self.next_bytes(4)?);
+        Ok(Duration::new(sec, nsec))
     }
 }
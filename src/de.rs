@@ -0,0 +1,302 @@
+//! Serde deserializer for the ROS serialization format.
+//!
+//! Fields get deserialized in the order of their declaration, so it should
+//! match order of fields in the message definition. Types get mapped as
+//! follows:
+//!
+//! - primitive ROS types to the Rust primitives of the same size;
+//! - `string` to `String` or `&str` (the latter borrows from the message data);
+//! - variable-size arrays to sequences (e.g. `Vec<T>`), `uint8[]` can be also
+//!   deserialized into `&[u8]` without copying;
+//! - fixed-size arrays to tuples or Rust arrays;
+//! - nested messages, `time` and `duration` to structs, the latter two can be
+//!   deserialized into [`Time`][crate::time::Time] and
+//!   [`Duration`][crate::time::Duration].
+//!
+//! # Example
+//! ```
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, Deserialize, PartialEq)]
+//! struct Time {
+//!     sec: u32,
+//!     nsec: u32,
+//! }
+//!
+//! #[derive(Debug, Deserialize, PartialEq)]
+//! struct Header<'a> {
+//!     seq: u32,
+//!     stamp: Time,
+//!     frame_id: &'a str,
+//! }
+//!
+//! # fn main() -> Result<(), rosbag::Error> {
+//! let data = b"\x01\x00\x00\x00\x02\x00\x00\x00\x03\x00\x00\x00\x03\x00\x00\x00map";
+//! let header: Header<'_> = rosbag::de::from_slice(data)?;
+//! assert_eq!(header, Header {
+//!     seq: 1,
+//!     stamp: Time { sec: 2, nsec: 3 },
+//!     frame_id: "map",
+//! });
+//! # Ok(()) }
+//! ```
+use crate::cursor::Cursor;
+use crate::{Error, ErrorKind, Result};
+use byteorder::{ByteOrder, LE};
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::str;
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(ErrorKind::InvalidMessageData(msg.to_string()))
+    }
+}
+
+/// Deserialize value from message data in the ROS serialization format.
+///
+/// Returns error if data contains trailing bytes.
+pub fn from_slice<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T> {
+    let mut de = Deserializer::new(data);
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+/// Deserializer for data in the ROS serialization format.
+pub struct Deserializer<'de> {
+    cursor: Cursor<'de>,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a new deserializer over serialized message data.
+    pub fn new(data: &'de [u8]) -> Self {
+        Self {
+            cursor: Cursor::new(data),
+        }
+    }
+
+    /// Check that all data was consumed.
+    pub fn end(&self) -> Result<()> {
+        match self.cursor.left() {
+            0 => Ok(()),
+            n => Err(Error::new(ErrorKind::InvalidMessageData(format!(
+                "{} trailing bytes",
+                n
+            )))),
+        }
+    }
+
+    fn next_bytes(&mut self, n: u64) -> Result<&'de [u8]> {
+        Ok(self.cursor.next_bytes(n)?)
+    }
+
+    fn next_len(&mut self) -> Result<usize> {
+        Ok(self.cursor.next_u32()? as usize)
+    }
+
+    fn next_str(&mut self) -> Result<&'de str> {
+        let data = self.cursor.next_chunk()?;
+        str::from_utf8(data).map_err(|e| Error::new(ErrorKind::InvalidMessageData(e.to_string())))
+    }
+}
+
+fn unsupported(tp: &str) -> Error {
+    Error::new(ErrorKind::InvalidMessageData(format!(
+        "{} is not supported by ROS serialization",
+        tp
+    )))
+}
+
+macro_rules! impl_primitive {
+    ($method:ident, $visit:ident, $n:expr, $read:expr) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let data = self.next_bytes($n)?;
+            visitor.$visit($read(data))
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::new(ErrorKind::InvalidMessageData(
+            "ROS serialization format is not self-describing".to_string(),
+        )))
+    }
+
+    impl_primitive!(deserialize_bool, visit_bool, 1, |d: &[u8]| d[0] != 0);
+    impl_primitive!(deserialize_i8, visit_i8, 1, |d: &[u8]| d[0] as i8);
+    impl_primitive!(deserialize_u8, visit_u8, 1, |d: &[u8]| d[0]);
+    impl_primitive!(deserialize_i16, visit_i16, 2, LE::read_i16);
+    impl_primitive!(deserialize_u16, visit_u16, 2, LE::read_u16);
+    impl_primitive!(deserialize_i32, visit_i32, 4, LE::read_i32);
+    impl_primitive!(deserialize_u32, visit_u32, 4, LE::read_u32);
+    impl_primitive!(deserialize_i64, visit_i64, 8, LE::read_i64);
+    impl_primitive!(deserialize_u64, visit_u64, 8, LE::read_u64);
+    impl_primitive!(deserialize_f32, visit_f32, 4, LE::read_f32);
+    impl_primitive!(deserialize_f64, visit_f64, 8, LE::read_f64);
+    impl_primitive!(deserialize_char, visit_char, 1, |d: &[u8]| d[0] as char);
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.next_str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.cursor.next_chunk()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("Option"))
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.next_len()?;
+        let sized = true;
+        visitor.visit_seq(Access {
+            de: self,
+            len,
+            sized,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        let sized = false;
+        visitor.visit_seq(Access {
+            de: self,
+            len,
+            sized,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("map"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value> {
+        Err(unsupported("enum"))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("identifier"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("ignored any"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    len: usize,
+    /// Length is read from the data, so elements must not be zero-sized
+    sized: bool,
+}
+
+impl<'a, 'de> SeqAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        let start = self.de.cursor.pos();
+        let value = seed.deserialize(&mut *self.de)?;
+        if self.sized && self.len != 0 && self.de.cursor.pos() == start {
+            return Err(Error::new(ErrorKind::InvalidMessageData(format!(
+                "sequence of {} zero-sized elements",
+                self.len + 1
+            ))));
+        }
+        Ok(Some(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        if self.sized {
+            // elements of sequences take at least one byte
+            Some(self.len.min(self.de.cursor.left() as usize))
+        } else {
+            Some(self.len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_slice;
+    use crate::ErrorKind;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Empty {}
+
+    #[test]
+    fn zero_sized_elements() {
+        let v: Vec<Empty> = from_slice(&[1, 0, 0, 0]).unwrap();
+        assert_eq!(v, [Empty {}]);
+        let v: ((), [Empty; 3]) = from_slice(&[]).unwrap();
+        assert_eq!(v, ((), [Empty {}, Empty {}, Empty {}]));
+
+        let err = from_slice::<Vec<Empty>>(&[0xff, 0xff, 0xff, 0xff]).unwrap_err();
+        let msg = "sequence of 4294967295 zero-sized elements".to_string();
+        assert_eq!(err.kind(), &ErrorKind::InvalidMessageData(msg));
+    }
+}
@@ -1,52 +1,273 @@
 use crate::cursor::OutOfBounds;
+use crate::record_types::Compression;
 use std::convert::From;
-use std::fmt;
+use std::{error, fmt, io};
+
+type Source = Box<dyn error::Error + Send + Sync + 'static>;
 
 /// The error type for ROS bag file reading and parsing.
-#[derive(Debug)]
-pub enum Error {
-    /// Invalid headed.
+///
+/// Besides the [kind][Error::kind] of the error it carries location of
+/// the problem: offset of the record in the bag file, record type and
+/// header field, if they are known. Underlying I/O and decompression
+/// errors are available via [`source`][error::Error::source].
+pub struct Error(Box<Inner>);
+
+struct Inner {
+    kind: ErrorKind,
+    offset: Option<u64>,
+    chunk_offset: Option<u64>,
+    record: Option<&'static str>,
+    field: Option<String>,
+    source: Option<Source>,
+}
+
+/// A list specifying categories of [`Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// I/O error.
+    Io,
+    /// Invalid header.
     InvalidHeader,
+    /// Required header field is missing.
+    MissingField,
     /// Invalid record.
     InvalidRecord,
     /// Encountered unsupported version in record.
     UnsupportedVersion,
     /// Tried to access outside of rosbag file.
     OutOfBounds,
+    /// Bag header does not point to the index section, the bag has to be
+    /// reindexed.
+    MissingIndex,
     /// Got unexpected record type in the chunk section.
     UnexpectedChunkSectionRecord(&'static str),
     /// Got unexpected record type in the index section.
     UnexpectedIndexSectionRecord(&'static str),
     /// Got unexpected record type inside [`Chunk`][crate::record_types::Chunk] payload.
     UnexpectedMessageRecord(&'static str),
-    /// Bzip2 decompression failure.
-    Bzip2DecompressionError(String),
-    /// Lz4 decompression failure.
-    Lz4DecompressionError(String),
+    /// Chunk uses compression which is neither built-in nor provided by
+    /// a registered [codec][crate::codec].
+    UnsupportedCompression(String),
+    /// Chunk decompression failure.
+    Decompression(Compression),
+    /// Invalid message definition.
+    InvalidMessageDefinition(String),
+    /// Serialized message data does not match its definition.
+    InvalidMessageData(String),
+    /// Message refers to connection with the given ID which was not
+    /// registered in [`BagWriter`][crate::BagWriter].
+    UnknownConnection(u32),
+    /// Connection with the given ID is already registered in
+    /// [`BagWriter`][crate::BagWriter].
+    DuplicateConnection(u32),
+    /// Record does not fit into the 4 GiB size limit of chunks.
+    RecordTooLarge,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind) -> Self {
+        Error(Box::new(Inner {
+            kind,
+            offset: None,
+            chunk_offset: None,
+            record: None,
+            field: None,
+            source: None,
+        }))
+    }
+
+    pub(crate) fn with_source<E: Into<Source>>(kind: ErrorKind, source: E) -> Self {
+        let mut err = Self::new(kind);
+        err.0.source = Some(source.into());
+        err
+    }
+
+    /// Set offset of the record relative to the bag file beginning, if it
+    /// is not set yet.
+    pub(crate) fn at(mut self, offset: u64) -> Self {
+        self.0.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Set offset of the record in decompressed chunk data, if it is not
+    /// set yet.
+    pub(crate) fn at_chunk_offset(mut self, offset: u64) -> Self {
+        self.0.chunk_offset.get_or_insert(offset);
+        self
+    }
+
+    /// Set type of the record being parsed, if it is not set yet.
+    pub(crate) fn in_record(mut self, record: &'static str) -> Self {
+        self.0.record.get_or_insert(record);
+        self
+    }
+
+    /// Set name of the offending header field, if it is not set yet.
+    pub(crate) fn in_field(mut self, field: &str) -> Self {
+        if self.0.field.is_none() {
+            self.0.field = Some(field.to_string());
+        }
+        self
+    }
+
+    /// Get kind of the error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.kind
+    }
+
+    /// Get offset of the offending record relative to the bag file
+    /// beginning.
+    ///
+    /// For records stored inside chunks it is offset of the chunk, while
+    /// position inside the chunk is available via [`Error::chunk_offset`].
+    pub fn offset(&self) -> Option<u64> {
+        self.0.offset
+    }
+
+    /// Get offset of the offending record in decompressed chunk data.
+    pub fn chunk_offset(&self) -> Option<u64> {
+        self.0.chunk_offset
+    }
+
+    /// Get type of the offending record, e.g. `"ChunkInfo"`.
+    pub fn record(&self) -> Option<&'static str> {
+        self.0.record
+    }
+
+    /// Get name of the offending header field.
+    pub fn field(&self) -> Option<&str> {
+        self.0.field.as_deref()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
+    }
 }
 
 impl From<OutOfBounds> for Error {
     fn from(_: OutOfBounds) -> Error {
-        Error::OutOfBounds
+        Error::new(ErrorKind::OutOfBounds)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        // unwrap errors converted into `io::Error` by `From<Error>`
+        if err.get_ref().map(|e| e.is::<Error>()).unwrap_or(false) {
+            let inner = err.into_inner().expect("error is present");
+            return *inner.downcast::<Error>().expect("type is checked");
+        }
+        Error::with_source(ErrorKind::Io, err)
+    }
+}
+
+/// Converts I/O errors without location back into the original
+/// `io::Error`. Other errors get wrapped into `io::Error` of the kind of
+/// the underlying I/O error or of the
+/// [`InvalidData`][io::ErrorKind::InvalidData] kind.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        let io_kind = match &err.0.source {
+            Some(source) if err.kind() == &ErrorKind::Io => {
+                source.downcast_ref::<io::Error>().map(|e| e.kind())
+            }
+            _ => None,
+        };
+        let e = &err.0;
+        let no_context = e.offset.is_none() && e.chunk_offset.is_none() && e.record.is_none();
+        match io_kind {
+            Some(_) if no_context && e.field.is_none() => {
+                let source = err.0.source.expect("source is present");
+                *source.downcast::<io::Error>().expect("type is checked")
+            }
+            Some(kind) => io::Error::new(kind, err),
+            None => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            Io => write!(f, "I/O error"),
+            InvalidHeader => write!(f, "invalid header"),
+            MissingField => write!(f, "missing header field"),
+            InvalidRecord => write!(f, "invalid record"),
+            UnsupportedVersion => write!(f, "unsupported version"),
+            OutOfBounds => write!(f, "out of bounds"),
+            MissingIndex => write!(f, "missing index section"),
+            UnexpectedChunkSectionRecord(t) => write!(f, "unexpected {} in the chunk section", t),
+            UnexpectedIndexSectionRecord(t) => write!(f, "unexpected {} in the index section", t),
+            UnexpectedMessageRecord(t) => write!(f, "unexpected {} in chunk payload", t),
+            UnsupportedCompression(c) => write!(f, "unsupported compression `{}`", c),
+            Decompression(c) => write!(f, "{} decompression error", c.as_str()),
+            InvalidMessageDefinition(e) => write!(f, "invalid message definition: {}", e),
+            InvalidMessageData(e) => write!(f, "invalid message data: {}", e),
+            UnknownConnection(id) => write!(f, "unknown connection {}", id),
+            DuplicateConnection(id) => write!(f, "connection {} is already registered", id),
+            RecordTooLarge => write!(f, "record exceeds the 4 GiB chunk size limit"),
+        }
     }
 }
 
+/// Formats the error kind followed by the known location, e.g.
+/// `rosbag::Error: invalid header in field `time` of MessageData record at
+/// offset 160 of chunk at offset 4974`.
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use Error::*;
-        let s = match self {
-            InvalidHeader => "invalid header".to_string(),
-            InvalidRecord => "invalid record".to_string(),
-            UnsupportedVersion => "unsupported version".to_string(),
-            OutOfBounds => "out of bounds".to_string(),
-            UnexpectedChunkSectionRecord(t) => format!("unexpected {} in the chunk section", t),
-            UnexpectedIndexSectionRecord(t) => format!("unexpected {} in the index section", t),
-            UnexpectedMessageRecord(t) => format!("unexpected {} in chunk payload", t),
-            Bzip2DecompressionError(e) => format!("bzip2 decompression error: {}", e),
-            Lz4DecompressionError(e) => format!("LZ4 decompression error: {}", e),
-        };
-        write!(f, "rosbag::Error: {}", s)
+        let e = &self.0;
+        write!(f, "rosbag::Error: {}", e.kind)?;
+        match (&e.field, e.record) {
+            (Some(field), Some(record)) => write!(f, " in field `{}` of {} record", field, record)?,
+            (Some(field), None) => write!(f, " in field `{}`", field)?,
+            (None, Some(record)) => write!(f, " in {} record", record)?,
+            (None, None) => {}
+        }
+        match (e.chunk_offset, e.offset) {
+            (Some(pos), Some(chunk)) => {
+                write!(f, " at offset {} of chunk at offset {}", pos, chunk)
+            }
+            (Some(pos), None) => write!(f, " at offset {} of chunk data", pos),
+            (None, Some(pos)) => write!(f, " at offset {}", pos),
+            (None, None) => Ok(()),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let e = &self.0;
+        let mut s = f.debug_struct("Error");
+        s.field("kind", &e.kind);
+        if let Some(offset) = e.offset {
+            s.field("offset", &offset);
+        }
+        if let Some(offset) = e.chunk_offset {
+            s.field("chunk_offset", &offset);
+        }
+        if let Some(record) = e.record {
+            s.field("record", &record);
+        }
+        if let Some(field) = &e.field {
+            s.field("field", field);
+        }
+        if let Some(source) = &e.source {
+            s.field("source", source);
+        }
+        s.finish()
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.0.source {
+            Some(source) => Some(source.as_ref()),
+            None => None,
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::record_types::{ChunkInfo, Connection, IndexData};
-use crate::{record::Record, Cursor, Error, Result};
+use crate::{record::Record, Cursor, Error, ErrorKind, Result};
 
 /// Record types which can be stored in the chunk section.
 #[derive(Debug, Clone)]
@@ -27,7 +27,7 @@ impl<'a> IndexRecordsIterator<'a> {
     /// data).
     pub fn seek(&mut self, pos: u64) -> Result<()> {
         if pos < self.offset {
-            return Err(Error::OutOfBounds);
+            return Err(Error::new(ErrorKind::OutOfBounds));
         }
         Ok(self.cursor.seek(pos - self.offset)?)
     }
@@ -40,13 +40,16 @@ impl<'a> Iterator for IndexRecordsIterator<'a> {
         if self.cursor.left() == 0 {
             return None;
         }
+        let pos = self.offset + self.cursor.pos();
         let res = match Record::next_record(&mut self.cursor) {
             Ok(Record::IndexData(v)) => Ok(IndexRecord::IndexData(v)),
             Ok(Record::Connection(v)) => Ok(IndexRecord::Connection(v)),
             Ok(Record::ChunkInfo(v)) => Ok(IndexRecord::ChunkInfo(v)),
-            Ok(v) => Err(Error::UnexpectedIndexSectionRecord(v.get_type())),
+            Ok(v) => Err(Error::new(ErrorKind::UnexpectedIndexSectionRecord(
+                v.get_type(),
+            ))),
             Err(e) => Err(e),
         };
-        Some(res)
+        Some(res.map_err(|e| e.at(pos)))
     }
 }
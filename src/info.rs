@@ -0,0 +1,605 @@
+//! Summary of bag contents equivalent to the output of `rosbag info`
+use crate::record_types::{Compression, RawChunk};
+use crate::time::{Duration, Time};
+use crate::{Result, RosBag};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+const NANOS: u64 = 1_000_000_000;
+
+/// Summary of bag contents computed by [`RosBag::info`].
+///
+/// The [`Display`][fmt::Display] implementation renders the human readable
+/// table printed by `rosbag info`, while [`BagInfo::to_yaml`] renders the
+/// output of `rosbag info --yaml`. Unlike the Python tool, dates are
+/// printed in UTC.
+#[derive(Debug, Clone)]
+pub struct BagInfo<'a> {
+    /// Path to the bag file, printed only if set
+    pub path: Option<String>,
+    /// Size of the bag file in bytes
+    pub size: u64,
+    /// Receive time of the earliest message
+    pub start_time: Time,
+    /// Receive time of the latest message
+    pub end_time: Time,
+    /// Total number of messages
+    pub message_count: u64,
+    /// Chunk statistics per compression type sorted by descending number
+    /// of chunks
+    pub compression: Vec<CompressionInfo>,
+    /// Message types sorted by name
+    pub types: Vec<TypeInfo<'a>>,
+    /// Topics sorted by name
+    pub topics: Vec<TopicStats<'a>>,
+}
+
+/// Statistics of chunks which use the same compression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionInfo {
+    /// Compression type
+    pub compression: Compression,
+    /// Number of chunks
+    pub chunk_count: u64,
+    /// Size of chunk data stored in the bag in bytes
+    pub compressed_size: u64,
+    /// Size of decompressed chunk data in bytes
+    pub uncompressed_size: u64,
+}
+
+/// Message type stored in the bag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeInfo<'a> {
+    /// Message type
+    pub tp: &'a str,
+    /// MD5 sum of the message type
+    pub md5sum: [u8; 16],
+}
+
+/// Statistics of messages stored on a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicStats<'a> {
+    /// Topic on which the messages are stored
+    pub topic: &'a str,
+    /// Message type
+    pub tp: &'a str,
+    /// Number of messages
+    pub message_count: u64,
+    /// Number of connections which recorded messages on the topic
+    pub connection_count: usize,
+    /// Frequency in Hz computed from the median period between messages,
+    /// `None` if it can not be estimated
+    pub frequency: Option<f64>,
+}
+
+impl<'a> BagInfo<'a> {
+    pub(crate) fn new<D: AsRef<[u8]>>(bag: &'a RosBag<D>) -> Result<Self> {
+        if let Some(err) = bag.index_error() {
+            return Err(err);
+        }
+        let topics = bag.topics();
+        let mut topic_ids = HashMap::new();
+        for (i, topic) in topics.iter().enumerate() {
+            for conn in topic.connections.iter() {
+                topic_ids.insert(conn.id, i);
+            }
+        }
+
+        let mut start_time = Time::MAX;
+        let mut end_time = Time::ZERO;
+        let mut compression: Vec<CompressionInfo> = Vec::new();
+        let mut stamps = vec![Vec::new(); topics.len()];
+        for info in bag.chunk_infos() {
+            start_time = start_time.min(info.start_time);
+            end_time = end_time.max(info.end_time);
+
+            let mut c = bag.chunk_cursor(info.chunk_pos)?;
+            let chunk = RawChunk::read(&mut c).map_err(|e| e.at(info.chunk_pos))?;
+            let pos = compression
+                .iter()
+                .position(|s| s.compression == chunk.compression)
+                .unwrap_or_else(|| {
+                    compression.push(CompressionInfo {
+                        compression: chunk.compression,
+                        chunk_count: 0,
+                        compressed_size: 0,
+                        uncompressed_size: 0,
+                    });
+                    compression.len() - 1
+                });
+            let stats = &mut compression[pos];
+            stats.chunk_count += 1;
+            stats.compressed_size += chunk.data.len() as u64;
+            stats.uncompressed_size += chunk.size as u64;
+
+            for index in bag.chunk_index(&info)? {
+                let i = match topic_ids.get(&index.conn_id) {
+                    Some(&i) => i,
+                    None => {
+                        let (pos, id) = (info.chunk_pos, index.conn_id);
+                        log::warn!(
+                            "chunk at {} has index data for unknown connection {}",
+                            pos,
+                            id
+                        );
+                        continue;
+                    }
+                };
+                stamps[i].extend(index.entries().map(|e| e.time.as_nanos()));
+            }
+        }
+        if start_time > end_time {
+            start_time = Time::ZERO;
+        }
+        compression.sort_by_key(|s| (Reverse(s.chunk_count), Reverse(s.compression.as_str())));
+
+        let mut types: Vec<TypeInfo<'a>> = Vec::new();
+        for conn in bag.connections() {
+            if types.iter().all(|t| t.tp != conn.tp) {
+                types.push(TypeInfo {
+                    tp: conn.tp,
+                    md5sum: conn.md5sum,
+                });
+            }
+        }
+        types.sort_by(|a, b| a.tp.cmp(b.tp));
+
+        let topics = topics
+            .into_iter()
+            .zip(stamps)
+            .map(|(topic, stamps)| TopicStats {
+                topic: topic.topic,
+                tp: topic.tp,
+                message_count: topic.message_count,
+                connection_count: topic.connections.len(),
+                frequency: median_frequency(stamps),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            path: None,
+            size: bag.bytes().len() as u64,
+            start_time,
+            end_time,
+            message_count: topics.iter().map(|t| t.message_count).sum(),
+            compression,
+            types,
+            topics,
+        })
+    }
+
+    /// Duration of the bag.
+    ///
+    /// Saturates at [`Duration::MAX`] for time ranges exceeding it.
+    pub fn duration(&self) -> Duration {
+        let duration = self.end_time.checked_duration_since(self.start_time);
+        duration.unwrap_or(Duration::MAX)
+    }
+
+    /// Total number of chunks.
+    pub fn chunk_count(&self) -> u64 {
+        self.compression.iter().map(|s| s.chunk_count).sum()
+    }
+
+    /// Total size of chunk data stored in the bag in bytes.
+    pub fn compressed_size(&self) -> u64 {
+        self.compression.iter().map(|s| s.compressed_size).sum()
+    }
+
+    /// Total size of decompressed chunk data in bytes.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.compression.iter().map(|s| s.uncompressed_size).sum()
+    }
+
+    fn all_uncompressed(&self) -> bool {
+        self.compression
+            .iter()
+            .all(|s| s.compression == Compression::None)
+    }
+
+    /// Render the summary in the layout of `rosbag info --yaml`.
+    ///
+    /// In addition to the fields printed by the Python tool, topic entries
+    /// contain the `frequency` field if it can be estimated.
+    pub fn to_yaml(&self) -> String {
+        let mut s = String::new();
+        // writing into `String` can not fail
+        let _ = self.write_yaml(&mut s);
+        s
+    }
+
+    fn write_yaml(&self, s: &mut String) -> fmt::Result {
+        if let Some(path) = &self.path {
+            writeln!(s, "path: {}", path)?;
+        }
+        writeln!(s, "version: 2.0")?;
+        writeln!(s, "duration: {}", Micros(self.duration().as_nanos() as u64))?;
+        writeln!(s, "start: {}", Micros(self.start_time.as_nanos()))?;
+        writeln!(s, "end: {}", Micros(self.end_time.as_nanos()))?;
+        writeln!(s, "size: {}", self.size)?;
+        writeln!(s, "messages: {}", self.message_count)?;
+        writeln!(s, "indexed: True")?;
+        match self.compression.first() {
+            Some(stats) => writeln!(s, "compression: {}", stats.compression.as_str())?,
+            None => writeln!(s, "compression: none")?,
+        }
+        if !self.all_uncompressed() {
+            writeln!(s, "uncompressed: {}", self.uncompressed_size())?;
+            writeln!(s, "compressed: {}", self.compressed_size())?;
+        }
+        writeln!(s, "types:")?;
+        for tp in self.types.iter() {
+            writeln!(s, "    - type: {}", tp.tp)?;
+            writeln!(s, "      md5: {}", Hex(&tp.md5sum))?;
+        }
+        writeln!(s, "topics:")?;
+        for topic in self.topics.iter() {
+            writeln!(s, "    - topic: {}", topic.topic)?;
+            writeln!(s, "      type: {}", topic.tp)?;
+            writeln!(s, "      messages: {}", topic.message_count)?;
+            if topic.connection_count > 1 {
+                writeln!(s, "      connections: {}", topic.connection_count)?;
+            }
+            if let Some(freq) = topic.frequency {
+                writeln!(s, "      frequency: {:.4}", freq)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = Vec::new();
+        if let Some(path) = &self.path {
+            rows.push(("path", path.clone()));
+        }
+        rows.push(("version", "2.0".to_string()));
+        if self.chunk_count() != 0 {
+            rows.push(("duration", format_duration(self.duration())));
+            rows.push(("start", format_time(self.start_time)));
+            rows.push(("end", format_time(self.end_time)));
+        }
+        rows.push(("size", human_size(self.size as f64)));
+        rows.push(("messages", self.message_count.to_string()));
+
+        let chunk_count = self.chunk_count();
+        let compressions = self
+            .compression
+            .iter()
+            .map(|stats| {
+                let name = stats.compression.as_str();
+                match stats.compression {
+                    Compression::None => {
+                        format!("{} [{}/{} chunks]", name, stats.chunk_count, chunk_count)
+                    }
+                    _ => format!(
+                        "{} [{}/{} chunks; {:.2}%]",
+                        name,
+                        stats.chunk_count,
+                        chunk_count,
+                        percent(stats.compressed_size, stats.uncompressed_size),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        match compressions.is_empty() {
+            true => rows.push(("compression", "none".to_string())),
+            false => rows.push(("compression", compressions.join(", "))),
+        }
+
+        if !self.all_uncompressed() {
+            let (uncompressed, compressed) = (self.uncompressed_size(), self.compressed_size());
+            let uncompressed_str = human_size(uncompressed as f64);
+            let compressed_str = human_size(compressed as f64);
+            let w = uncompressed_str.len().max(compressed_str.len());
+            let duration = self.duration().as_secs_f64();
+            if duration > 0.0 {
+                let uncompressed_rate = human_size(uncompressed as f64 / duration);
+                let compressed_rate = human_size(compressed as f64 / duration);
+                let rw = uncompressed_rate.len().max(compressed_rate.len());
+                rows.push((
+                    "uncompressed",
+                    format!(
+                        "{:>w$} @ {:>rw$}/s",
+                        uncompressed_str,
+                        uncompressed_rate,
+                        w = w,
+                        rw = rw,
+                    ),
+                ));
+                rows.push((
+                    "compressed",
+                    format!(
+                        "{:>w$} @ {:>rw$}/s ({:.2}%)",
+                        compressed_str,
+                        compressed_rate,
+                        percent(compressed, uncompressed),
+                        w = w,
+                        rw = rw,
+                    ),
+                ));
+            } else {
+                rows.push(("uncompressed", format!("{:>w$}", uncompressed_str, w = w)));
+                rows.push(("compressed", format!("{:>w$}", compressed_str, w = w)));
+            }
+        }
+
+        let type_w = self.types.iter().map(|t| t.tp.len()).max().unwrap_or(0);
+        for (i, tp) in self.types.iter().enumerate() {
+            let name = if i == 0 { "types" } else { "" };
+            rows.push((
+                name,
+                format!("{:<w$} [{}]", tp.tp, Hex(&tp.md5sum), w = type_w),
+            ));
+        }
+
+        let topic_w = self.topics.iter().map(|t| t.topic.len()).max().unwrap_or(0);
+        let count_w = self
+            .topics
+            .iter()
+            .map(|t| t.message_count.to_string().len());
+        let count_w = count_w.max().unwrap_or(0);
+        let freqs = self.topics.iter().filter_map(|t| t.frequency);
+        let freq_w = freqs.map(|f| human_frequency(f).len()).max().unwrap_or(0);
+        for (i, topic) in self.topics.iter().enumerate() {
+            let n = topic.message_count;
+            let msgs = if n > 1 { "msgs" } else { "msg " };
+            let mut s = format!(
+                "{:<tw$}   {:>cw$} {}",
+                topic.topic,
+                n,
+                msgs,
+                tw = topic_w,
+                cw = count_w,
+            );
+            match topic.frequency {
+                Some(freq) => s += &format!(" @ {:>w$}", human_frequency(freq), w = freq_w),
+                None => s += &format!("   {:>w$}", " ", w = freq_w),
+            }
+            s += &format!(" : {:<w$}", topic.tp, w = type_w);
+            if topic.connection_count > 1 {
+                s += &format!(" ({} connections)", topic.connection_count);
+            }
+            let name = if i == 0 { "topics" } else { "" };
+            rows.push((name, s));
+        }
+        rows
+    }
+}
+
+impl<'a> fmt::Display for BagInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.rows();
+        let w = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0) + 1;
+        let mut s = String::new();
+        for (name, value) in rows {
+            let name = if name.is_empty() {
+                String::new()
+            } else {
+                format!("{}:", name)
+            };
+            writeln!(s, "{:<w$} {}", name, value, w = w)?;
+        }
+        f.write_str(s.trim_end())
+    }
+}
+
+/// Estimate frequency from the median period between sorted timestamps.
+fn median_frequency(mut stamps: Vec<u64>) -> Option<f64> {
+    if stamps.len() < 2 {
+        return None;
+    }
+    stamps.sort_unstable();
+    let mut periods: Vec<u64> = stamps.windows(2).map(|w| w[1] - w[0]).collect();
+    periods.sort_unstable();
+    let n = periods.len();
+    let median = match n % 2 {
+        0 => (periods[n / 2 - 1] + periods[n / 2]) as f64 / 2.0,
+        _ => periods[n / 2] as f64,
+    };
+    if median > 0.0 {
+        Some(NANOS as f64 / median)
+    } else {
+        None
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => 100.0 * part as f64 / total as f64,
+    }
+}
+
+fn human_size(mut size: f64) -> String {
+    for suffix in ["KB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB"] {
+        size /= 1024.0;
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, suffix);
+        }
+    }
+    "-".to_string()
+}
+
+fn human_frequency(mut freq: f64) -> String {
+    for suffix in ["Hz", "kHz", "MHz", "GHz", "THz", "PHz", "EHz", "ZHz", "YHz"] {
+        if freq < 1000.0 {
+            return format!("{:.1} {}", freq, suffix);
+        }
+        freq /= 1000.0;
+    }
+    "-".to_string()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let duration = duration.as_nanos() as u64;
+    let secs = duration / NANOS;
+    let (hrs, mins) = (secs / 3600, secs / 60 % 60);
+    if hrs > 0 {
+        format!("{}hr {}:{:02}s ({}s)", hrs, mins, secs % 60, secs)
+    } else if mins > 0 {
+        format!("{}:{:02}s ({}s)", mins, secs % 60, secs)
+    } else {
+        format!("{:.1}s", duration as f64 / NANOS as f64)
+    }
+}
+
+/// Format time as e.g. `Jul 18 2019 14:37:54.88 (1563460674.88)`.
+fn format_time(time: Time) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let centis = (time.as_nanos() + 5_000_000) / 10_000_000;
+    let (secs, frac) = (centis / 100, centis % 100);
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let (h, m, s) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    format!(
+        "{} {:02} {} {:02}:{:02}:{:02}.{:02} ({}.{:02})",
+        MONTHS[month as usize - 1],
+        day,
+        year,
+        h,
+        m,
+        s,
+        frac,
+        secs,
+        frac,
+    )
+}
+
+/// Convert number of days since UNIX epoch to `(year, month, day)` in the
+/// proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Time in seconds with microsecond precision.
+struct Micros(u64);
+
+impl fmt::Display for Micros {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let us = (self.0 + 500) / 1000;
+        write!(f, "{}.{:06}", us / 1_000_000, us % 1_000_000)
+    }
+}
+
+/// Lowercase hexadecimal representation of bytes, e.g. of MD5 sums.
+///
+/// ```
+/// let md5sum = [0x99, 0x2c, 0xe8, 0xa1];
+/// assert_eq!(rosbag::Hex(&md5sum).to_string(), "992ce8a1");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::percent;
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::RosBag;
+
+    #[test]
+    fn table() {
+        let bag = RosBag::from_bytes(write_bag(Compression::Bzip2, 10, 4)).unwrap();
+        let mut info = bag.info().unwrap();
+        info.path = Some("test.bag".to_string());
+        let expected = [
+            "path:         test.bag",
+            "version:      2.0",
+            "duration:     9.0s",
+            "start:        Jan 01 1970 00:00:00.00 (0.00)",
+            "end:          Jan 01 1970 00:00:09.00 (9.00)",
+            "size:         7.2 KB",
+            "messages:     20",
+            "compression:  bz2 [5/5 chunks; 47.76%]",
+            "uncompressed: 2.6 KB @ 0.3 KB/s",
+            "compressed:   1.2 KB @ 0.1 KB/s (47.76%)",
+            "types:        std_msgs/String [992ce8a1687cec8c8bd883ec73ca41d1]",
+            "topics:       /a   10 msgs @ 1.0 Hz : std_msgs/String",
+            "              /b   10 msgs @ 1.0 Hz : std_msgs/String",
+        ];
+        assert_eq!(info.to_string(), expected.join("\n"));
+
+        let bag = RosBag::from_bytes(write_bag(Compression::None, 0, 4)).unwrap();
+        let expected = [
+            "version:     2.0",
+            "size:        4.3 KB",
+            "messages:    0",
+            "compression: none",
+            "types:       std_msgs/String [992ce8a1687cec8c8bd883ec73ca41d1]",
+            "topics:      /a   0 msg      : std_msgs/String",
+            "             /b   0 msg      : std_msgs/String",
+        ];
+        assert_eq!(bag.info().unwrap().to_string(), expected.join("\n"));
+    }
+
+    #[test]
+    fn yaml() {
+        let bag = RosBag::from_bytes(write_bag(Compression::Bzip2, 10, 4)).unwrap();
+        let expected = "\
+version: 2.0
+duration: 9.001000
+start: 0.000000
+end: 9.001000
+size: 7333
+messages: 20
+indexed: True
+compression: bz2
+uncompressed: 2630
+compressed: 1256
+types:
+    - type: std_msgs/String
+      md5: 992ce8a1687cec8c8bd883ec73ca41d1
+topics:
+    - topic: /a
+      type: std_msgs/String
+      messages: 10
+      frequency: 1.0000
+    - topic: /b
+      type: std_msgs/String
+      messages: 10
+      frequency: 1.0000
+";
+        assert_eq!(bag.info().unwrap().to_yaml(), expected);
+    }
+
+    #[test]
+    fn unknown_connection() {
+        let mut data = write_bag(Compression::None, 10, 4);
+        // change ID of the `Connection` record in the index section
+        let pattern = b"conn=\x01\x00\x00\x00";
+        let pos = data
+            .windows(pattern.len())
+            .rposition(|w| w == pattern)
+            .unwrap();
+        data[pos + 5] = 7;
+        let bag = RosBag::from_bytes(data).unwrap();
+        let info = bag.info().unwrap();
+        assert_eq!(info.topics.len(), 2);
+        assert_eq!(info.topics[0].frequency, Some(1.0));
+        assert_eq!(info.topics[1].frequency, None);
+    }
+
+    #[test]
+    fn zero_percent() {
+        assert_eq!(percent(0, 0), 0.0);
+        assert_eq!(percent(1, 4), 25.0);
+    }
+}
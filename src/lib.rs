@@ -1,4 +1,4 @@
-//! Utilities for efficient reading of ROS bag files.
+//! Utilities for efficient reading and writing of ROS bag files.
 //!
 //! # Example
 //! ```
@@ -54,38 +54,98 @@
 #![warn(missing_docs, rust_2018_idioms)]
 
 use memmap2::Mmap;
-use std::{fs, io, path::Path, result, str};
+use std::collections::BTreeMap;
+use std::io::{Seek, Write};
+use std::sync::Mutex;
+use std::{fs, path::Path, result, str};
 
 const VERSION_STRING: &str = "#ROSBAG V2.0\n";
 const VERSION_LEN: u64 = VERSION_STRING.len() as u64;
 const ROSBAG_HEADER_OP: u8 = 0x03;
+/// Bag header record type name used in errors
+pub(crate) const BAG_HEADER: &str = "BagHeader";
 
+#[cfg(feature = "async")]
+mod async_reader;
+mod bag_index;
+mod cache;
+pub mod codec;
 mod cursor;
+#[cfg(feature = "serde")]
+pub mod de;
 mod error;
 mod field_iter;
+mod info;
+mod reader;
 mod record;
+mod recovery;
+mod reindex;
+mod shared;
+mod stream;
+#[cfg(test)]
+mod test_utils;
+mod verify;
 
 mod chunk_iter;
 mod index_iter;
+mod messages;
 mod msg_iter;
+#[cfg(feature = "rayon")]
+mod parallel;
 pub mod record_types;
+pub mod schema;
+pub mod time;
+pub mod value;
+mod writer;
 
+use bag_index::BagIndex;
+use cache::ChunkCache;
 use cursor::Cursor;
 use field_iter::FieldIterator;
 use record_types::utils::{check_op, set_field_u32, set_field_u64};
+use record_types::{ChunkInfo, Connection, IndexData, RawChunk, RecordGen};
+use reindex::RebuiltIndex;
 
+#[cfg(feature = "async")]
+pub use async_reader::AsyncBagReader;
+pub use bag_index::TopicInfo;
 pub use chunk_iter::{ChunkRecord, ChunkRecordsIterator};
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 pub use index_iter::{IndexRecord, IndexRecordsIterator};
-pub use msg_iter::{MessageRecord, MessageRecordsIterator};
+pub use info::{BagInfo, CompressionInfo, Hex, TopicStats, TypeInfo};
+pub use messages::{Message, Messages, OwnedMessage, SharedMessages};
+pub use msg_iter::{
+    MessageRecord, MessageRecordsIterator, OwnedMessageRecord, OwnedMessageRecordsIterator,
+};
+pub use reader::BagReader;
+pub use recovery::{RecoveredRecord, RecoveryIterator};
+pub use shared::{SharedBag, SharedChunkRecords};
+pub use stream::BagStream;
+pub use verify::{Issue, IssueKind, VerifyReport};
+pub use writer::BagWriter;
 
 /// Open rosbag file.
-pub struct RosBag {
-    data: Mmap,
+pub struct RosBag<D = Mmap> {
+    data: D,
     start_pos: usize,
     index_pos: usize,
     conn_count: u32,
     chunk_count: u32,
+    index: BagIndex,
+    /// Index rebuilt from the chunk section by [`RosBag::reindex`]
+    rebuilt: Option<RebuiltIndex>,
+    /// Decompressed chunks reused by chunk and message iterators
+    cache: Mutex<ChunkCache>,
+}
+
+/// Connection which stored MD5 sum does not match its message definition.
+#[derive(Debug)]
+pub struct Md5Mismatch<'a> {
+    /// Connection with mismatched MD5 sum
+    pub conn: Connection<'a>,
+    /// MD5 sum computed from the message definition or error which occurred
+    /// while parsing the definition
+    pub computed: Result<[u8; 16]>,
 }
 
 /// A specialized Result type for ROS bag file reading and parsing.
@@ -106,64 +166,208 @@ fn parse_bag_header(data: &[u8]) -> Result<(u64, BagHeader)> {
     let mut cursor = Cursor::new(data);
 
     if cursor.next_bytes(VERSION_LEN)? != VERSION_STRING.as_bytes() {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::UnsupportedVersion).at(0));
     }
 
-    let header = cursor.next_chunk()?;
+    let read = |c: &mut Cursor<'_>| -> Result<BagHeader> {
+        let header = read_bag_header(c.next_chunk()?)?;
+        // jump over header data
+        c.next_chunk()?;
+        Ok(header)
+    };
+    let header = read(&mut cursor).map_err(|e| e.in_record(BAG_HEADER).at(VERSION_LEN))?;
+
+    Ok((cursor.pos(), header))
+}
 
+/// Error for bag header which `index_pos` does not point to the index section.
+pub(crate) fn missing_index() -> Error {
+    let err = Error::new(ErrorKind::MissingIndex).in_field("index_pos");
+    err.in_record(BAG_HEADER).at(VERSION_LEN)
+}
+
+/// Parse fields of the bag header record.
+fn read_bag_header(header: &[u8]) -> Result<BagHeader> {
     let mut index_pos: Option<u64> = None;
     let mut conn_count: Option<u32> = None;
     let mut chunk_count: Option<u32> = None;
     let mut op: bool = false;
 
     for item in FieldIterator::new(header) {
-        let (name, val) = item?;
-        match name {
-            "op" => {
-                check_op(val, ROSBAG_HEADER_OP)?;
-                op = true;
+        let (name, val) = item.map_err(|e| e.in_record(BAG_HEADER))?;
+        let res = match name {
+            "op" => check_op(val, ROSBAG_HEADER_OP).map(|()| op = true),
+            "index_pos" => set_field_u64(&mut index_pos, val),
+            "conn_count" => set_field_u32(&mut conn_count, val),
+            "chunk_count" => set_field_u32(&mut chunk_count, val),
+            _ => {
+                log::warn!("unexpected field in bag header: {}", name);
+                Ok(())
             }
-            "index_pos" => set_field_u64(&mut index_pos, val)?,
-            "conn_count" => set_field_u32(&mut conn_count, val)?,
-            "chunk_count" => set_field_u32(&mut chunk_count, val)?,
-            _ => log::warn!("unexpected field in bag header: {}", name),
-        }
+        };
+        res.map_err(|e| e.in_field(name).in_record(BAG_HEADER))?;
     }
 
-    let bag_header = match (index_pos, conn_count, chunk_count, op) {
-        (Some(index_pos), Some(conn_count), Some(chunk_count), true) => BagHeader {
-            index_pos,
-            conn_count,
-            chunk_count,
-        },
-        _ => return Err(Error::InvalidHeader),
+    let missing = |name| {
+        Error::new(ErrorKind::MissingField)
+            .in_field(name)
+            .in_record(BAG_HEADER)
     };
-
-    // jump over header data
-    let _ = cursor.next_chunk()?;
-
-    Ok((cursor.pos(), bag_header))
+    if !op {
+        return Err(missing("op"));
+    }
+    Ok(BagHeader {
+        index_pos: index_pos.ok_or_else(|| missing("index_pos"))?,
+        conn_count: conn_count.ok_or_else(|| missing("conn_count"))?,
+        chunk_count: chunk_count.ok_or_else(|| missing("chunk_count"))?,
+    })
 }
 
 impl RosBag {
     /// Create a new iterator over provided path to ROS bag file.
-    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    ///
+    /// The file is memory mapped, use [`RosBag::from_bytes`] or
+    /// [`BagReader`] if memory mapping is not suitable.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let data = unsafe { Mmap::map(&fs::File::open(path)?)? };
+        Self::from_bytes(data)
+    }
 
-        let (start_pos, header) = parse_bag_header(&data).map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid or unsupported rosbag header",
-            )
-        })?;
+    /// Open ROS bag file ignoring its index section and rebuild the index by
+    /// scanning the chunk section sequentially.
+    ///
+    /// This allows to work with bags which recording was interrupted before
+    /// the index section was written, in which case `index_pos` in the bag
+    /// header is zero or points past the end of file. Scanning stops at the
+    /// first truncated or invalid record, so data after it becomes
+    /// inaccessible. Chunks are not required to be followed by `IndexData`
+    /// records, since they are rebuilt from the chunk contents.
+    ///
+    /// The rebuilt index is kept in memory, use [`RosBag::write_reindexed`]
+    /// to create a repaired copy of the bag.
+    pub fn reindex<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = unsafe { Mmap::map(&fs::File::open(path)?)? };
+        Self::reindex_bytes(data)
+    }
+}
+
+impl<D: AsRef<[u8]>> RosBag<D> {
+    /// Open ROS bag stored in memory.
+    ///
+    /// `data` can be any byte container, e.g. `&[u8]`, `Vec<u8>` or
+    /// `bytes::Bytes`.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let data: Vec<u8> = std::fs::read("dummy.bag")?;
+    /// let bag = rosbag::RosBag::from_bytes(data)?;
+    /// # drop(bag);
+    /// # Ok(()) }
+    /// ```
+    pub fn from_bytes(data: D) -> Result<Self> {
+        let (start_pos, header) = parse_bag_header(data.as_ref())?;
+
+        let len = data.as_ref().len() as u64;
+        if header.index_pos < start_pos || header.index_pos > len {
+            return Err(missing_index());
+        }
 
-        Ok(Self {
+        let bag = Self {
             data,
             start_pos: start_pos.try_into().unwrap(),
             conn_count: header.conn_count,
             index_pos: header.index_pos.try_into().unwrap(),
             chunk_count: header.chunk_count,
-        })
+            index: BagIndex::default(),
+            rebuilt: None,
+            cache: Default::default(),
+        };
+        Ok(bag.build_index())
+    }
+
+    /// Same as [`RosBag::reindex`], but for ROS bag stored in memory.
+    pub fn reindex_bytes(data: D) -> Result<Self> {
+        let (start_pos, header) = parse_bag_header(data.as_ref())?;
+        let len = data.as_ref().len() as u64;
+        if header.index_pos != 0 && header.index_pos <= len {
+            log::info!("bag has index section at {}, ignoring it", header.index_pos);
+        }
+        let rebuilt = RebuiltIndex::scan(data.as_ref(), start_pos)?;
+
+        let bag = Self {
+            data,
+            start_pos: start_pos.try_into().unwrap(),
+            conn_count: rebuilt.conn_count,
+            index_pos: rebuilt.end_pos.try_into().unwrap(),
+            chunk_count: rebuilt.chunk_count,
+            index: BagIndex::default(),
+            rebuilt: Some(rebuilt),
+            cache: Default::default(),
+        };
+        Ok(bag.build_index())
+    }
+
+    /// Get the whole bag data.
+    fn bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    fn build_index(mut self) -> Self {
+        self.index = BagIndex::build(self.index_records());
+        if let Some(pos) = self.index.error_pos {
+            log::warn!(
+                "invalid index record at {}, ignoring the rest of index",
+                pos
+            );
+        }
+        self
+    }
+
+    /// Get error of the first record in the index section which can not be
+    /// read.
+    ///
+    /// Opening of a bag does not fail on invalid index records, instead
+    /// records following the invalid one are ignored and the error is
+    /// reported by [`RosBag::info`], [`RosBag::messages`],
+    /// [`RosBag::write_reindexed`] and [`RosBag::verify`]. The chunk section
+    /// can still be read using [`RosBag::chunk_records`] and
+    /// [`RosBag::recover_records`] or reindexed with [`RosBag::reindex`].
+    pub fn index_error(&self) -> Option<Error> {
+        self.index.error(self.index_records())
+    }
+
+    /// Write a copy of the bag with a correct index section into `writer`.
+    ///
+    /// Chunks are copied without recompression and get followed by rebuilt
+    /// `IndexData` records. Chunks which do not contain messages are omitted.
+    /// Together with [`RosBag::reindex`] this is equivalent to
+    /// `rosbag reindex`.
+    pub fn write_reindexed<W: Write + Seek>(&self, writer: W) -> Result<W> {
+        if let Some(err) = self.index_error() {
+            return Err(err);
+        }
+        let mut out = BagWriter::new(writer)?;
+        for conn in self.connections() {
+            out.add_connection(&conn)?;
+        }
+        let mut infos: Vec<ChunkInfo<'_>> = self.chunk_infos().collect();
+        infos.sort_by_key(|info| info.chunk_pos);
+        for info in infos.iter() {
+            let mut c = self.chunk_cursor(info.chunk_pos)?;
+            RawChunk::read(&mut c).map_err(|e| e.at(info.chunk_pos))?;
+            let record = &self.bytes()[info.chunk_pos as usize..c.pos() as usize];
+
+            let mut index = BTreeMap::new();
+            for data in self.chunk_index(info)? {
+                let entries = data.entries().map(|e| (e.time, e.offset));
+                index
+                    .entry(data.conn_id)
+                    .or_insert_with(Vec::new)
+                    .extend(entries);
+            }
+            out.write_raw_chunk(record, info.start_time, info.end_time, &index)?;
+        }
+        out.finish()
     }
 
     /// Get connection count in this rosbag file.
@@ -178,19 +382,199 @@ impl RosBag {
 
     /// Get iterator over records in the chunk section.
     pub fn chunk_records(&self) -> ChunkRecordsIterator<'_> {
-        let cursor = Cursor::new(&self.data[self.start_pos..self.index_pos]);
+        let cursor = Cursor::new(&self.bytes()[self.start_pos..self.index_pos]);
         ChunkRecordsIterator {
             cursor,
             offset: self.start_pos as u64,
+            cache: Some(&self.cache),
         }
     }
 
+    /// Set maximum total size in bytes of decompressed chunks kept in the
+    /// chunk cache.
+    ///
+    /// Chunks decompressed by [`RosBag::chunk_records`] and
+    /// [`RosBag::messages`] are cached by their position and evicted in the
+    /// least recently used order, so repeated random access to the same
+    /// chunks, e.g. using [`ChunkRecordsIterator::seek`], does not
+    /// decompress them again. Chunks larger than the capacity and
+    /// uncompressed chunks are not cached. The cache is disabled by default.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let bag = rosbag::RosBag::new("dummy.bag")?;
+    /// bag.set_chunk_cache_capacity(256 << 20);
+    /// # Ok(()) }
+    /// ```
+    pub fn set_chunk_cache_capacity(&self, bytes: usize) {
+        cache::lock(&self.cache).set_capacity(bytes);
+    }
+
+    /// Get iterator over records in the chunk section which recovers from
+    /// corrupted and truncated records instead of stopping at them.
+    ///
+    /// For bags opened with [`RosBag::reindex`] the iterator continues past
+    /// the end of the scanned chunk section up to the end of file.
+    pub fn recover_records(&self) -> RecoveryIterator<'_> {
+        let end = match self.rebuilt {
+            Some(_) => self.bytes().len(),
+            None => self.index_pos,
+        };
+        let mut cursor = Cursor::new(&self.bytes()[..end]);
+        cursor
+            .seek(self.start_pos as u64)
+            .expect("start position is in bounds");
+        RecoveryIterator { cursor }
+    }
+
     /// Get iterator over records in the index section.
+    ///
+    /// For bags opened with [`RosBag::reindex`] the iterator yields records
+    /// of the rebuilt index.
     pub fn index_records(&self) -> IndexRecordsIterator<'_> {
-        let cursor = Cursor::new(&self.data[self.index_pos..]);
+        let data = match &self.rebuilt {
+            Some(rebuilt) => &rebuilt.section,
+            None => &self.bytes()[self.index_pos..],
+        };
+        let cursor = Cursor::new(data);
         IndexRecordsIterator {
             cursor,
             offset: self.index_pos as u64,
         }
     }
+
+    /// Get connection with the given ID.
+    pub fn connection(&self, id: u32) -> Option<Connection<'_>> {
+        self.index.connection(id)
+    }
+
+    /// Get iterator over all connections in the bag sorted by their IDs.
+    pub fn connections(&self) -> impl Iterator<Item = Connection<'_>> {
+        self.index.connections()
+    }
+
+    /// Get iterator over connections which messages are stored on `topic`.
+    pub fn topic_connections<'a>(
+        &'a self,
+        topic: &'a str,
+    ) -> impl Iterator<Item = Connection<'a>> + 'a {
+        self.index.topic_connections(topic)
+    }
+
+    /// Get number of messages which arrived on connection with the given ID.
+    pub fn message_count(&self, conn_id: u32) -> u64 {
+        self.index.message_count(conn_id)
+    }
+
+    /// Get information about topics stored in the bag sorted by topic name.
+    pub fn topics(&self) -> Vec<TopicInfo<'_>> {
+        self.index.topics()
+    }
+
+    /// Get iterator over `ChunkInfo` records in the index section.
+    pub(crate) fn chunk_infos(&self) -> impl Iterator<Item = ChunkInfo<'_>> {
+        self.index.chunk_infos()
+    }
+
+    /// Get cursor over the chunk section positioned at `pos`.
+    pub(crate) fn chunk_cursor(&self, pos: u64) -> Result<Cursor<'_>> {
+        let mut c = Cursor::new(&self.bytes()[..self.index_pos]);
+        if pos < self.start_pos as u64 {
+            return Err(Error::new(ErrorKind::OutOfBounds).at(pos));
+        }
+        c.seek(pos).map_err(|e| Error::from(e).at(pos))?;
+        Ok(c)
+    }
+
+    /// Read `IndexData` records of the chunk described by `info`.
+    pub(crate) fn chunk_index(&self, info: &ChunkInfo<'_>) -> Result<Vec<IndexData<'_>>> {
+        let mut res = Vec::new();
+        match &self.rebuilt {
+            Some(rebuilt) => {
+                let data = rebuilt.chunk_index.get(&info.chunk_pos);
+                let mut c = Cursor::new(data.ok_or(Error::new(ErrorKind::MissingIndex))?);
+                while c.left() != 0 {
+                    res.push(IndexData::read(c.next_chunk()?, &mut c)?);
+                }
+            }
+            None => {
+                let mut c = self.chunk_cursor(info.chunk_pos)?;
+                RawChunk::read(&mut c).map_err(|e| e.at(info.chunk_pos))?;
+                for _ in info.entries() {
+                    let pos = c.pos();
+                    let header = c.next_chunk().map_err(Error::from);
+                    let rec = header.and_then(|header| IndexData::read(header, &mut c));
+                    res.push(rec.map_err(|e| e.at(pos))?);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Compute summary of the bag contents similar to the output of
+    /// `rosbag info`.
+    ///
+    /// Chunks are not decompressed, but `IndexData` records which follow
+    /// them are read to estimate topic frequencies.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let bag = rosbag::RosBag::new("dummy.bag")?;
+    /// let mut info = bag.info()?;
+    /// info.path = Some("dummy.bag".to_string());
+    /// println!("{}", info);
+    /// print!("{}", info.to_yaml());
+    /// # Ok(()) }
+    /// ```
+    pub fn info(&self) -> Result<BagInfo<'_>> {
+        BagInfo::new(self)
+    }
+
+    /// Walk the whole file and cross-check the index section against the
+    /// chunk section.
+    ///
+    /// The check confirms that every `ChunkInfo` record points at a chunk,
+    /// that its message counts and time range match the chunk contents, that
+    /// every `IndexData` entry points at a message with the same connection
+    /// and time, and that counts stored in the bag header are correct.
+    /// Found problems are reported with positions of the affected records.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let bag = rosbag::RosBag::new("dummy.bag")?;
+    /// let report = bag.verify();
+    /// if !report.is_ok() {
+    ///     println!("{}", report);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn verify(&self) -> VerifyReport {
+        verify::verify(self)
+    }
+
+    /// Get iterator over messages stored in the bag sorted by their receive
+    /// time.
+    ///
+    /// If the index section contains a record which can not be read, the
+    /// iterator yields its error and ends.
+    pub fn messages(&self) -> Messages<'_, D> {
+        Messages::new(self)
+    }
+
+    /// Find connections which MD5 sums do not match their message
+    /// definitions.
+    ///
+    /// Messages on such connections were most likely recorded with locally
+    /// modified message types and can not be reliably decoded using the
+    /// stored definitions.
+    pub fn check_md5sums(&self) -> Vec<Md5Mismatch<'_>> {
+        let mut res = Vec::new();
+        for conn in self.connections() {
+            let computed = conn.compute_md5sum();
+            if computed.as_ref().ok() != Some(&conn.md5sum) {
+                res.push(Md5Mismatch { conn, computed });
+            }
+        }
+        res
+    }
 }
@@ -0,0 +1,717 @@
+//! Time-ordered iteration over messages stored in a bag
+use crate::cache;
+#[cfg(feature = "rayon")]
+use crate::parallel::Prefetch;
+use crate::record_types::{ChunkBytes, ChunkInfo, Connection, OwnedConnection, RawChunk};
+use crate::time::Time;
+use crate::{Error, ErrorKind, MessageRecord, MessageRecordsIterator, Result, RosBag};
+use memmap2::Mmap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+/// Message together with its connection.
+///
+/// Data of messages from compressed chunks is shared with other messages
+/// from the same chunk, so cloning `Message` is cheap.
+#[derive(Debug, Clone)]
+pub struct Message<'a> {
+    /// Connection on which the message arrived
+    pub conn: Connection<'a>,
+    /// Time at which the message was received
+    pub time: Time,
+    chunk: ChunkBytes<'a>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Message<'a> {
+    /// Create message which data is stored in `chunk[start..end]`.
+    #[cfg(feature = "async")]
+    pub(crate) fn from_shared(
+        conn: Connection<'a>,
+        time: Time,
+        chunk: Arc<Vec<u8>>,
+        start: usize,
+        end: usize,
+    ) -> Self {
+        Self {
+            conn,
+            time,
+            chunk: ChunkBytes::Shared(chunk),
+            start,
+            end,
+        }
+    }
+
+    /// Serialized message data in the ROS serialization format.
+    pub fn data(&self) -> &[u8] {
+        &self.chunk.as_slice()[self.start..self.end]
+    }
+
+    /// Deserialize message data into `T`.
+    ///
+    /// See the [`de`][crate::de] module for details of the type mapping.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<'de, T: serde::Deserialize<'de>>(&'de self) -> Result<T> {
+        crate::de::from_slice(self.data())
+    }
+
+    /// Convert into message which does not borrow from the bag and can be
+    /// sent to other threads.
+    ///
+    /// Messages from compressed chunks keep sharing the decompressed chunk
+    /// data, while data of messages from uncompressed chunks gets copied.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use rosbag::RosBag;
+    /// use std::sync::mpsc;
+    ///
+    /// let bag = RosBag::new("dummy.bag")?;
+    /// let (tx, rx) = mpsc::channel();
+    /// let worker = std::thread::spawn(move || {
+    ///     for msg in rx {
+    ///         let msg: rosbag::OwnedMessage = msg;
+    ///         // ..
+    ///         # drop(msg);
+    ///     }
+    /// });
+    /// for msg in bag.messages() {
+    ///     tx.send(msg?.into_owned())?;
+    /// }
+    /// drop(tx);
+    /// worker.join().unwrap();
+    /// # Ok(()) }
+    /// ```
+    pub fn into_owned(self) -> OwnedMessage {
+        let (chunk, start, end) = match self.chunk {
+            ChunkBytes::Shared(chunk) => (chunk, self.start, self.end),
+            ChunkBytes::Borrowed(data) => {
+                let data = data[self.start..self.end].to_vec();
+                let end = data.len();
+                (Arc::new(data), 0, end)
+            }
+        };
+        OwnedMessage {
+            conn: Arc::new(self.conn.into_owned()),
+            time: self.time,
+            chunk,
+            start,
+            end,
+        }
+    }
+}
+
+/// Message together with its connection which does not borrow from the bag.
+///
+/// See [`Message::into_owned`].
+#[derive(Debug, Clone)]
+pub struct OwnedMessage {
+    /// Connection on which the message arrived, shared between messages
+    /// yielded by [`SharedMessages`]
+    pub conn: Arc<OwnedConnection>,
+    /// Time at which the message was received
+    pub time: Time,
+    chunk: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl OwnedMessage {
+    /// Serialized message data in the ROS serialization format.
+    pub fn data(&self) -> &[u8] {
+        &self.chunk[self.start..self.end]
+    }
+
+    /// Deserialize message data into `T`.
+    ///
+    /// See the [`de`][crate::de] module for details of the type mapping.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<'de, T: serde::Deserialize<'de>>(&'de self) -> Result<T> {
+        crate::de::from_slice(self.data())
+    }
+}
+
+/// Chunk which messages are being currently yielded.
+struct OpenChunk<'a> {
+    /// Offset of the chunk record in the bag file
+    pos: u64,
+    data: ChunkBytes<'a>,
+    /// `(time, offset, conn_id)` entries sorted by time
+    entries: Vec<(Time, u32, u32)>,
+}
+
+struct State<'a, C> {
+    connections: HashMap<u32, C>,
+    /// IDs of connections which messages should be yielded, `None` for all
+    selected: Option<HashSet<u32>>,
+    /// Chunks which were not opened yet sorted by start time
+    chunks: VecDeque<ChunkInfo<'a>>,
+    /// Open chunks indexed by their sequence number
+    open: HashMap<usize, OpenChunk<'a>>,
+    /// Number of opened chunks
+    opened: usize,
+    /// Next entry of each open chunk: `(time, chunk sequence number, entry index)`
+    heap: BinaryHeap<Reverse<(Time, usize, usize)>>,
+    #[cfg(feature = "rayon")]
+    prefetch: Option<Prefetch>,
+}
+
+/// Bag handle from which messages get read, either borrowed or shared.
+pub(crate) trait MessageSource<'a> {
+    type Data: AsRef<[u8]>;
+    /// Connection stored in the yielded messages
+    type Conn: Clone;
+
+    fn bag(&self) -> &RosBag<Self::Data>;
+
+    fn connections(&self) -> HashMap<u32, Self::Conn>;
+
+    fn chunk_infos(&self) -> Vec<ChunkInfo<'a>>;
+
+    /// Read and decompress data of the chunk described by `info`.
+    fn chunk_data(&self, info: &ChunkInfo<'_>) -> Result<ChunkBytes<'a>>;
+}
+
+impl<'a, D: AsRef<[u8]>> MessageSource<'a> for &'a RosBag<D> {
+    type Data = D;
+    type Conn = Connection<'a>;
+
+    fn bag(&self) -> &RosBag<D> {
+        self
+    }
+
+    fn connections(&self) -> HashMap<u32, Connection<'a>> {
+        let bag: &'a RosBag<D> = self;
+        bag.connections().map(|conn| (conn.id, conn)).collect()
+    }
+
+    fn chunk_infos(&self) -> Vec<ChunkInfo<'a>> {
+        let bag: &'a RosBag<D> = self;
+        bag.chunk_infos().collect()
+    }
+
+    fn chunk_data(&self, info: &ChunkInfo<'_>) -> Result<ChunkBytes<'a>> {
+        read_chunk_data(self, info)
+    }
+}
+
+impl<D: AsRef<[u8]>> MessageSource<'static> for Arc<RosBag<D>> {
+    type Data = D;
+    type Conn = Arc<OwnedConnection>;
+
+    fn bag(&self) -> &RosBag<D> {
+        self
+    }
+
+    fn connections(&self) -> HashMap<u32, Arc<OwnedConnection>> {
+        let conns = RosBag::connections(self);
+        conns
+            .map(|conn| (conn.id, Arc::new(conn.into_owned())))
+            .collect()
+    }
+
+    fn chunk_infos(&self) -> Vec<ChunkInfo<'static>> {
+        RosBag::chunk_infos(self)
+            .map(ChunkInfo::into_owned)
+            .collect()
+    }
+
+    fn chunk_data(&self, info: &ChunkInfo<'_>) -> Result<ChunkBytes<'static>> {
+        let data = read_chunk_data(self, info)?;
+        Ok(ChunkBytes::Shared(data.into_shared()))
+    }
+}
+
+/// Message read from an open chunk.
+struct RawMessage<'a, C> {
+    conn: C,
+    time: Time,
+    chunk: ChunkBytes<'a>,
+    start: usize,
+    end: usize,
+}
+
+/// Time-ordered iteration shared by [`Messages`] and [`SharedMessages`].
+struct MessageIter<'a, S, C> {
+    bag: S,
+    topics: Option<Vec<String>>,
+    conn_ids: Option<HashSet<u32>>,
+    start_time: Time,
+    end_time: Time,
+    #[cfg(feature = "rayon")]
+    window: usize,
+    state: Option<State<'a, C>>,
+}
+
+/// Filter methods of message iterators which set fields of the inner
+/// [`MessageIter`].
+macro_rules! filter_methods {
+    () => {
+        /// Yield only messages stored on the provided topics.
+        ///
+        /// Topics are matched against [`Connection::storage_topic`]. Filters
+        /// should be set before the iteration starts, otherwise they are ignored.
+        pub fn topics<I>(mut self, topics: I) -> Self
+        where
+            I: IntoIterator,
+            I::Item: AsRef<str>,
+        {
+            let topics = topics.into_iter().map(|t| t.as_ref().to_string());
+            self.inner.topics = Some(topics.collect());
+            self
+        }
+
+        /// Yield only messages which arrived on connections with the provided IDs.
+        ///
+        /// If used together with the `topics` filter, messages have to
+        /// satisfy both filters.
+        pub fn connections<I: IntoIterator<Item = u32>>(mut self, conn_ids: I) -> Self {
+            self.inner.conn_ids = Some(conn_ids.into_iter().collect());
+            self
+        }
+
+        /// Yield only messages received at or after `time`.
+        pub fn start_time(mut self, time: Time) -> Self {
+            self.inner.start_time = time;
+            self
+        }
+
+        /// Yield only messages received at or before `time`.
+        pub fn end_time(mut self, time: Time) -> Self {
+            self.inner.end_time = time;
+            self
+        }
+
+        /// Decompress up to `window` upcoming chunks ahead of the consumer on
+        /// the rayon thread pool.
+        ///
+        /// Messages are yielded in the same order as without parallel
+        /// decompression. Memory use grows by up to `window` decompressed
+        /// chunks together with copies of their compressed data. Should be set
+        /// before the iteration starts, otherwise it is ignored.
+        ///
+        /// ```
+        /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+        /// # let bag = rosbag::RosBag::new("dummy.bag")?;
+        /// for msg in bag.messages().parallel(8) {
+        ///     // ..
+        ///     # drop(msg?);
+        /// }
+        /// # Ok(()) }
+        /// ```
+        #[cfg(feature = "rayon")]
+        pub fn parallel(mut self, window: usize) -> Self {
+            self.inner.window = window;
+            self
+        }
+    };
+}
+
+/// Iterator over messages stored in a bag, sorted by their receive time.
+///
+/// The iterator uses information from the index section to merge messages
+/// from chunks with overlapping time ranges. Chunks get decompressed only
+/// when their messages are about to be yielded, so at any given moment only
+/// chunks with overlapping time ranges are kept in memory.
+///
+/// Iteration can be restricted to a subset of topics or connections and to
+/// a time range, in which case chunks without matching messages are skipped
+/// without decompression, while messages inside chunks are located using
+/// `IndexData` records.
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let bag = rosbag::RosBag::new("dummy.bag")?;
+/// for msg in bag.messages().topics(["/imu", "/odom"]) {
+///     let msg = msg?;
+///     println!("{} {}: {} bytes", msg.time, msg.conn.topic, msg.data().len());
+/// }
+/// # Ok(()) }
+/// ```
+///
+/// Created by the [`RosBag::messages`] method.
+pub struct Messages<'a, D = Mmap> {
+    inner: MessageIter<'a, &'a RosBag<D>, Connection<'a>>,
+}
+
+impl<'a, D: AsRef<[u8]>> Messages<'a, D> {
+    pub(crate) fn new(bag: &'a RosBag<D>) -> Self {
+        Self {
+            inner: MessageIter::new(bag),
+        }
+    }
+
+    filter_methods!();
+}
+
+impl<'a, D: AsRef<[u8]>> Iterator for Messages<'a, D> {
+    type Item = Result<Message<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.inner.next()?;
+        Some(res.map(|msg| Message {
+            conn: msg.conn,
+            time: msg.time,
+            chunk: msg.chunk,
+            start: msg.start,
+            end: msg.end,
+        }))
+    }
+}
+
+/// Iterator over messages stored in a shared bag, sorted by their receive
+/// time.
+///
+/// Same as [`Messages`], but holds a clone of the bag handle and yields
+/// [`OwnedMessage`]s, so it can be stored together with the bag, returned
+/// from functions and sent to other threads. Messages share connections
+/// and decompressed chunk data, while uncompressed chunks get copied once
+/// on opening.
+///
+/// Created by the [`SharedBag::messages`][crate::SharedBag::messages] method.
+pub struct SharedMessages<D = Mmap> {
+    inner: MessageIter<'static, Arc<RosBag<D>>, Arc<OwnedConnection>>,
+}
+
+impl<D: AsRef<[u8]>> SharedMessages<D> {
+    pub(crate) fn new(bag: Arc<RosBag<D>>) -> Self {
+        Self {
+            inner: MessageIter::new(bag),
+        }
+    }
+
+    filter_methods!();
+}
+
+impl<D: AsRef<[u8]>> Iterator for SharedMessages<D> {
+    type Item = Result<OwnedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.inner.next()?;
+        Some(res.map(|msg| OwnedMessage {
+            conn: msg.conn,
+            time: msg.time,
+            chunk: msg.chunk.into_shared(),
+            start: msg.start,
+            end: msg.end,
+        }))
+    }
+}
+
+impl<'a, S: MessageSource<'a>> MessageIter<'a, S, S::Conn> {
+    fn new(bag: S) -> Self {
+        Self {
+            bag,
+            topics: None,
+            conn_ids: None,
+            start_time: Time::ZERO,
+            end_time: Time::MAX,
+            #[cfg(feature = "rayon")]
+            window: 0,
+            state: None,
+        }
+    }
+
+    fn init(&self) -> State<'a, S::Conn> {
+        let connections = self.bag.connections();
+        let mut chunks = self.bag.chunk_infos();
+
+        let mut selected = self.conn_ids.clone();
+        if let Some(topics) = &self.topics {
+            let ids = self
+                .bag
+                .bag()
+                .connections()
+                .filter(|conn| topics.iter().any(|t| t == conn.storage_topic))
+                .map(|conn| conn.id);
+            selected = Some(match selected {
+                Some(sel) => ids.filter(|id| sel.contains(id)).collect(),
+                None => ids.collect(),
+            });
+        }
+        if let Some(sel) = &selected {
+            chunks.retain(|info| info.entries().any(|e| sel.contains(&e.conn_id)));
+        }
+        let (start, end) = (self.start_time, self.end_time);
+        chunks.retain(|info| info.end_time >= start && info.start_time <= end);
+        // chunks which only partially overlap with the time range may have
+        // no selected messages inside of it, skip them without decompression
+        let bag = self.bag.bag();
+        chunks.retain(|info| {
+            info.start_time >= start && info.end_time <= end
+                || has_entries(bag, info, selected.as_ref(), (start, end))
+        });
+
+        chunks.sort_by_key(|info| (info.start_time, info.chunk_pos));
+        State {
+            connections,
+            selected,
+            chunks: chunks.into(),
+            open: HashMap::new(),
+            opened: 0,
+            heap: BinaryHeap::new(),
+            #[cfg(feature = "rayon")]
+            prefetch: match self.window {
+                0 => None,
+                window => Some(Prefetch::new(window)),
+            },
+        }
+    }
+
+    fn next_message(state: &mut State<'a, S::Conn>) -> Option<Result<RawMessage<'a, S::Conn>>> {
+        let Reverse((time, seq, idx)) = state.heap.pop()?;
+        let chunk = state.open.get(&seq).expect("chunk is open");
+        let (_, offset, conn_id) = chunk.entries[idx];
+        let data = chunk.data.clone();
+        let pos = chunk.pos;
+
+        match chunk.entries.get(idx + 1).map(|e| e.0) {
+            Some(time) => state.heap.push(Reverse((time, seq, idx + 1))),
+            None => {
+                state.open.remove(&seq);
+            }
+        }
+
+        let res = read_message(&state.connections, data, time, offset, conn_id);
+        Some(res.map_err(|e| e.at(pos)))
+    }
+}
+
+/// Read and decompress data of the chunk described by `info`.
+fn read_chunk_data<'a, D: AsRef<[u8]>>(
+    bag: &'a RosBag<D>,
+    info: &ChunkInfo<'_>,
+) -> Result<ChunkBytes<'a>> {
+    let mut c = bag.chunk_cursor(info.chunk_pos)?;
+    let raw = RawChunk::read(&mut c).map_err(|e| e.at(info.chunk_pos))?;
+    Ok(cache::decompress(&bag.cache, info.chunk_pos, raw)?.data)
+}
+
+/// Check that chunk described by `info` has index entries for selected
+/// connections inside of the time range.
+fn has_entries<D: AsRef<[u8]>>(
+    bag: &RosBag<D>,
+    info: &ChunkInfo<'_>,
+    selected: Option<&HashSet<u32>>,
+    (start, end): (Time, Time),
+) -> bool {
+    let indexes = match bag.chunk_index(info) {
+        Ok(indexes) => indexes,
+        // the error is reported when the chunk gets opened
+        Err(_) => return true,
+    };
+    indexes
+        .iter()
+        .filter(|index| {
+            selected
+                .map(|sel| sel.contains(&index.conn_id))
+                .unwrap_or(true)
+        })
+        .any(|index| index.entries().any(|e| e.time >= start && e.time <= end))
+}
+
+/// Collect index entries of chunk with decompressed `data` for selected
+/// connections.
+fn open_chunk<'a, D: AsRef<[u8]>>(
+    data: ChunkBytes<'a>,
+    bag: &RosBag<D>,
+    info: &ChunkInfo<'_>,
+    selected: Option<&HashSet<u32>>,
+    time_range: (Time, Time),
+) -> Result<OpenChunk<'a>> {
+    let mut entries = Vec::new();
+    for index in bag.chunk_index(info)? {
+        let conn_id = index.conn_id;
+        if selected.map(|sel| !sel.contains(&conn_id)).unwrap_or(false) {
+            continue;
+        }
+        entries.extend(index.entries().map(|e| (e.time, e.offset, conn_id)));
+    }
+    entries.sort_unstable();
+    let (start, end) = time_range;
+    let n = entries.partition_point(|e| e.0 <= end);
+    entries.truncate(n);
+    let n = entries.partition_point(|e| e.0 < start);
+    entries.drain(..n);
+    Ok(OpenChunk {
+        pos: info.chunk_pos,
+        data,
+        entries,
+    })
+}
+
+fn read_message<'a, C: Clone>(
+    connections: &HashMap<u32, C>,
+    chunk: ChunkBytes<'a>,
+    time: Time,
+    offset: u32,
+    conn_id: u32,
+) -> Result<RawMessage<'a, C>> {
+    let conn = connections.get(&conn_id).ok_or_else(|| {
+        let err = Error::new(ErrorKind::InvalidRecord).in_field("conn");
+        err.in_record("IndexData")
+    })?;
+    let mut iter = MessageRecordsIterator::new(chunk.as_slice());
+    iter.seek(offset)
+        .map_err(|e| e.at_chunk_offset(offset as u64))?;
+    let (start, end) = match iter.next() {
+        Some(Ok(MessageRecord::MessageData(msg))) if msg.conn_id == conn_id => {
+            let end = iter.cursor.pos() as usize;
+            (end - msg.data.len(), end)
+        }
+        Some(Err(err)) => return Err(err),
+        _ => {
+            let err = Error::new(ErrorKind::InvalidRecord).in_field("offset");
+            return Err(err.in_record("IndexData").at_chunk_offset(offset as u64));
+        }
+    };
+    Ok(RawMessage {
+        conn: conn.clone(),
+        time,
+        chunk,
+        start,
+        end,
+    })
+}
+
+impl<'a, S: MessageSource<'a>> Iterator for MessageIter<'a, S, S::Conn> {
+    type Item = Result<RawMessage<'a, S::Conn>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state.is_none() {
+            let err = self.bag.bag().index_error();
+            let mut state = self.init();
+            if let Some(err) = err {
+                state.chunks.clear();
+                self.state = Some(state);
+                return Some(Err(err));
+            }
+            self.state = Some(state);
+        }
+        let state = self.state.as_mut().expect("state is initialized");
+        let bag = self.bag.bag();
+
+        // open all chunks which may contain messages earlier than the next
+        // message of already open chunks
+        while let Some(info) = state.chunks.front() {
+            match state.heap.peek() {
+                Some(Reverse((time, ..))) if *time < info.start_time => break,
+                _ => {}
+            }
+            #[cfg(feature = "rayon")]
+            if let Some(prefetch) = &mut state.prefetch {
+                prefetch.submit(bag, state.chunks.iter());
+            }
+            let info = state.chunks.pop_front().expect("chunk is present");
+            #[cfg(feature = "rayon")]
+            let prefetched = match &mut state.prefetch {
+                Some(prefetch) => prefetch.take(info.chunk_pos),
+                None => None,
+            };
+            #[cfg(not(feature = "rayon"))]
+            let prefetched: Option<Result<Arc<Vec<u8>>>> = None;
+            let data = match prefetched {
+                Some(res) => res.map(|data| {
+                    cache::lock(&bag.cache).insert(info.chunk_pos, data.clone());
+                    ChunkBytes::Shared(data)
+                }),
+                None => self.bag.chunk_data(&info),
+            };
+            let selected = state.selected.as_ref();
+            let time_range = (self.start_time, self.end_time);
+            let res = data.and_then(|data| open_chunk(data, bag, &info, selected, time_range));
+            let chunk = match res.map_err(|e| e.at(info.chunk_pos)) {
+                Ok(chunk) => chunk,
+                Err(err) => return Some(Err(err)),
+            };
+            let seq = state.opened;
+            state.opened += 1;
+            if let Some(&(time, ..)) = chunk.entries.first() {
+                state.heap.push(Reverse((time, seq, 0)));
+                state.open.insert(seq, chunk);
+            }
+        }
+
+        Self::next_message(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::{Compression, MessageData};
+    use crate::test_utils::{connection, message_time, string_msg, write_bag};
+    use crate::time::Time;
+    use crate::{BagWriter, RosBag};
+    use std::io::Cursor;
+
+    #[test]
+    fn merge_order() {
+        // chunks with interleaved time ranges: [0, 2, 4], [1, 3, 5], [6]
+        let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_compression(Compression::Lz4);
+        writer.add_connection(&connection(0)).unwrap();
+        writer.add_connection(&connection(1)).unwrap();
+        for (i, &sec) in [0, 2, 4, 1, 3, 5, 6].iter().enumerate() {
+            let data = string_msg(&sec.to_string());
+            let msg = MessageData {
+                conn_id: sec % 2,
+                time: Time::new(sec, 0),
+                data: &data,
+            };
+            writer.write_message(&msg).unwrap();
+            if i % 3 == 2 {
+                writer.flush_chunk().unwrap();
+            }
+        }
+        let bag = RosBag::from_bytes(writer.finish().unwrap().into_inner()).unwrap();
+        assert_eq!(bag.get_chunk_count(), 3);
+        let messages = bag.messages().map(|msg| {
+            let msg = msg.unwrap();
+            assert_eq!(msg.conn.id, msg.time.sec() % 2);
+            assert_eq!(msg.data(), &string_msg(&msg.time.sec().to_string())[..]);
+            msg.time.sec()
+        });
+        assert_eq!(messages.collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn filter_counts() {
+        let bag = RosBag::from_bytes(write_bag(Compression::Lz4, 10, 4)).unwrap();
+        let (start, end) = (Time::new(3, 0), Time::new(5, 0));
+        assert_eq!(bag.messages().count(), 20);
+        assert_eq!(bag.messages().topics(["/a"]).count(), 10);
+        assert_eq!(bag.messages().topics(["/a", "/b"]).count(), 20);
+        assert_eq!(bag.messages().topics(["/c"]).count(), 0);
+        assert_eq!(bag.messages().connections([1]).count(), 10);
+        assert_eq!(bag.messages().topics(["/a"]).connections([1]).count(), 0);
+        // both bounds are inclusive
+        assert_eq!(bag.messages().start_time(start).end_time(end).count(), 5);
+        let messages = bag.messages().start_time(start).end_time(end);
+        assert_eq!(messages.topics(["/b"]).count(), 2);
+        assert_eq!(bag.messages().start_time(Time::new(10, 0)).count(), 0);
+        assert_eq!(bag.messages().end_time(message_time(0, 0)).count(), 1);
+    }
+
+    #[test]
+    fn boundary_chunks_are_skipped() {
+        // chunks: [0.000, 1.000], [1.001, 2.001], [3.000, 3.001]
+        let mut data = write_bag(Compression::Bzip2, 4, 3);
+        let pos = data.windows(3).position(|w| w == b"BZh").unwrap();
+        // corrupt the first chunk, which has no `/b` messages after 0.5 s
+        data[pos + 4..pos + 10].fill(0);
+        let bag = RosBag::from_bytes(data).unwrap();
+        let messages = bag
+            .messages()
+            .topics(["/b"])
+            .start_time(Time::new(0, 500_000_000));
+        let times: Vec<_> = messages.map(|msg| msg.unwrap().time).collect();
+        let expected: Vec<_> = (1..4).map(|i| message_time(1, i)).collect();
+        assert_eq!(times, expected);
+
+        // the chunk is still opened if it has messages in the time range
+        let mut messages = bag.messages().start_time(Time::new(0, 500_000_000));
+        assert!(messages.next().unwrap().is_err());
+    }
+}
@@ -1,9 +1,10 @@
 //! Iterators over content of `Chunk`
 use super::Result;
-use crate::record_types::{Connection, MessageData};
-use crate::{record::Record, Error};
+use crate::record_types::{Connection, MessageData, OwnedConnection, OwnedMessageData};
+use crate::{record::Record, Error, ErrorKind};
 
 use crate::cursor::Cursor;
+use std::sync::Arc;
 
 /// Record types which can be stored in a [`Chunk`][crate::record_types::Chunk] record.
 #[derive(Debug, Clone)]
@@ -48,12 +49,67 @@ impl<'a> Iterator for MessageRecordsIterator<'a> {
         if self.cursor.left() == 0 {
             return None;
         }
+        let pos = self.cursor.pos();
         let res = match Record::next_record(&mut self.cursor) {
             Ok(Record::MessageData(v)) => Ok(MessageRecord::MessageData(v)),
             Ok(Record::Connection(v)) => Ok(MessageRecord::Connection(v)),
-            Ok(v) => Err(Error::UnexpectedMessageRecord(v.get_type())),
+            Ok(v) => Err(Error::new(ErrorKind::UnexpectedMessageRecord(v.get_type()))),
             Err(e) => Err(e),
         };
+        Some(res.map_err(|e| e.at_chunk_offset(pos)))
+    }
+}
+
+/// Record types which can be stored in a [`Chunk`][crate::record_types::Chunk]
+/// record, see [`Chunk::owned_messages`][crate::record_types::Chunk::owned_messages].
+#[derive(Debug, Clone)]
+pub enum OwnedMessageRecord {
+    /// [`OwnedMessageData`] record.
+    MessageData(OwnedMessageData),
+    /// [`OwnedConnection`] record.
+    Connection(OwnedConnection),
+}
+
+/// Iterator over owned records stored in a [`Chunk`][crate::record_types::Chunk]
+/// record.
+///
+/// The iterator shares decompressed chunk data with the yielded
+/// [`OwnedMessageData`] records. After an error the iterator yields no
+/// further items.
+pub struct OwnedMessageRecordsIterator {
+    data: Arc<Vec<u8>>,
+    pos: u64,
+}
+
+impl OwnedMessageRecordsIterator {
+    pub(crate) fn new(data: Arc<Vec<u8>>) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl Iterator for OwnedMessageRecordsIterator {
+    type Item = Result<OwnedMessageRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut records = MessageRecordsIterator::new(&self.data);
+        records.cursor.seek(self.pos).ok()?;
+        let res = match records.next()? {
+            Ok(MessageRecord::MessageData(msg)) => {
+                let end = records.cursor.pos() as usize;
+                let start = end - msg.data.len();
+                let data = self.data.clone();
+                let msg = OwnedMessageData::from_shared(msg.conn_id, msg.time, data, start, end);
+                Ok(OwnedMessageRecord::MessageData(msg))
+            }
+            Ok(MessageRecord::Connection(conn)) => {
+                Ok(OwnedMessageRecord::Connection(conn.into_owned()))
+            }
+            Err(err) => Err(err),
+        };
+        self.pos = match res {
+            Ok(_) => records.cursor.pos(),
+            Err(_) => self.data.len() as u64,
+        };
         Some(res)
     }
 }
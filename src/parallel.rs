@@ -0,0 +1,129 @@
+//! Decompression of upcoming chunks on the rayon thread pool
+use crate::cache;
+use crate::record_types::{ChunkInfo, Compression, RawChunk};
+use crate::{Result, RosBag};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+
+/// Decompressed chunk data or decompression error.
+type Decompressed = Result<Arc<Vec<u8>>>;
+
+/// Chunks decompressed ahead of the consumer.
+pub(crate) struct Prefetch {
+    /// Maximum number of chunks being decompressed or waiting to be taken
+    window: usize,
+    /// Jobs and receivers of decompressed chunk data by chunk position
+    pending: HashMap<u64, (Arc<Job>, mpsc::Receiver<Decompressed>)>,
+}
+
+/// Decompression of a single chunk, run either by the thread pool or by the
+/// consumer, whichever claims its data first.
+struct Job {
+    compression: Compression,
+    size: u32,
+    data: Mutex<Option<Vec<u8>>>,
+}
+
+impl Job {
+    /// Decompress the chunk, returns `None` if it was already claimed.
+    fn run(&self) -> Option<Decompressed> {
+        let data = self
+            .data
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()?;
+        let raw = RawChunk {
+            compression: self.compression,
+            size: self.size,
+            data: &data,
+        };
+        Some(raw.decompress().map(|chunk| chunk.data.into_shared()))
+    }
+}
+
+impl Prefetch {
+    pub(crate) fn new(window: usize) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Start decompression of chunks which are among the first `window`
+    /// of `upcoming` chunks.
+    ///
+    /// Uncompressed chunks and chunks which can not be read are skipped,
+    /// they are handled by the consumer.
+    pub(crate) fn submit<'a, 'b: 'a, D: AsRef<[u8]>>(
+        &mut self,
+        bag: &RosBag<D>,
+        upcoming: impl Iterator<Item = &'a ChunkInfo<'b>>,
+    ) {
+        for info in upcoming.take(self.window) {
+            let pos = info.chunk_pos;
+            if self.pending.contains_key(&pos) || cache::lock(&bag.cache).contains(pos) {
+                continue;
+            }
+            let raw = bag
+                .chunk_cursor(pos)
+                .and_then(|mut c| RawChunk::read(&mut c));
+            let raw = match raw {
+                Ok(raw) if raw.compression != Compression::None => raw,
+                _ => continue,
+            };
+            let job = Arc::new(Job {
+                compression: raw.compression,
+                size: raw.size,
+                data: Mutex::new(Some(raw.data.to_vec())),
+            });
+            let (tx, rx) = mpsc::sync_channel(1);
+            let spawned = job.clone();
+            rayon::spawn(move || {
+                if let Some(res) = spawned.run() {
+                    // the consumer may be already dropped
+                    let _ = tx.send(res);
+                }
+            });
+            self.pending.insert(pos, (job, rx));
+        }
+    }
+
+    /// Take decompressed data of chunk at `pos`, blocking until it is ready.
+    ///
+    /// If the pool has not started decompression of the chunk yet, it gets
+    /// decompressed on the current thread. Waiting for a queued job could
+    /// deadlock if the consumer itself runs on a busy pool thread.
+    ///
+    /// Returns `None` if decompression of the chunk was not submitted.
+    pub(crate) fn take(&mut self, pos: u64) -> Option<Decompressed> {
+        let (job, rx) = self.pending.remove(&pos)?;
+        match job.run() {
+            Some(res) => Some(res),
+            // the job is running or finished on the pool
+            None => rx.recv().ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::RosBag;
+
+    #[test]
+    fn single_thread_pool() {
+        let bag = RosBag::from_bytes(write_bag(Compression::Bzip2, 20, 5)).unwrap();
+        let expected: Vec<_> = bag.messages().map(|msg| msg.unwrap().time).collect();
+        // decompression jobs get queued behind the consumer on the only thread
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let times: Vec<_> = pool.install(|| {
+            let messages = bag.messages().parallel(4);
+            messages.map(|msg| msg.unwrap().time).collect()
+        });
+        assert_eq!(times, expected);
+    }
+}
@@ -0,0 +1,267 @@
+//! On-demand reading of bags from `Read + Seek` sources
+use crate::bag_index::BagIndex;
+use crate::cursor::Cursor;
+use crate::record_types::{Chunk, ChunkBytes, ChunkInfo, Connection, RawChunk};
+use crate::{
+    missing_index, read_bag_header, Error, ErrorKind, IndexRecordsIterator, Result, BAG_HEADER,
+    VERSION_LEN, VERSION_STRING,
+};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Reader of ROS bags stored in a `Read + Seek` source.
+///
+/// Unlike [`RosBag`][crate::RosBag], which requires the whole file to be
+/// memory mapped or loaded, `BagReader` keeps in memory only the bag header
+/// and the index section. Chunks are read from the source on demand, which
+/// makes it suitable for network filesystems, files inside archives and
+/// other sources which can not be memory mapped.
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use rosbag::BagReader;
+///
+/// let file = std::fs::File::open("dummy.bag")?;
+/// let mut reader = BagReader::new(std::io::BufReader::new(file))?;
+/// let positions: Vec<u64> = reader.chunk_infos().map(|info| info.chunk_pos).collect();
+/// for pos in positions {
+///     let chunk = reader.read_chunk(pos)?;
+///     for msg in chunk.messages() {
+///         // ..
+///         # drop(msg?);
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+pub struct BagReader<R> {
+    reader: R,
+    index: LoadedIndex,
+}
+
+/// Check that record part of `n` bytes, which starts after `read` bytes of
+/// the record at `pos`, does not exceed source length `len`.
+pub(crate) fn check_record_len(pos: u64, read: usize, n: u32, len: u64) -> Result<()> {
+    match pos + read as u64 + n as u64 > len {
+        true => Err(Error::new(ErrorKind::OutOfBounds).at(pos)),
+        false => Ok(()),
+    }
+}
+
+/// Bag header and index section loaded into memory.
+pub(crate) struct LoadedIndex {
+    /// Length of the source in bytes
+    pub(crate) len: u64,
+    pub(crate) start_pos: u64,
+    pub(crate) index_pos: u64,
+    pub(crate) conn_count: u32,
+    pub(crate) chunk_count: u32,
+    /// Data of the index section
+    data: Vec<u8>,
+    index: BagIndex,
+}
+
+impl LoadedIndex {
+    /// Check version string read from the source beginning.
+    pub(crate) fn check_version(version: &[u8]) -> Result<()> {
+        match version == VERSION_STRING.as_bytes() {
+            true => Ok(()),
+            false => Err(Error::new(ErrorKind::UnsupportedVersion).at(0)),
+        }
+    }
+
+    /// Parse bag header `record` which follows the version string in
+    /// a source of `len` bytes.
+    pub(crate) fn new(len: u64, record: &[u8]) -> Result<Self> {
+        let header = Cursor::new(record)
+            .next_chunk()
+            .map_err(Error::from)
+            .and_then(read_bag_header)
+            .map_err(|e| e.in_record(BAG_HEADER).at(VERSION_LEN))?;
+        let start_pos = VERSION_LEN + record.len() as u64;
+        if header.index_pos < start_pos || header.index_pos > len {
+            return Err(missing_index());
+        }
+        Ok(Self {
+            len,
+            start_pos,
+            index_pos: header.index_pos,
+            conn_count: header.conn_count,
+            chunk_count: header.chunk_count,
+            data: Vec::new(),
+            index: BagIndex::default(),
+        })
+    }
+
+    /// Validate and index data of the index section.
+    pub(crate) fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        self.data = data;
+        self.index = BagIndex::build(self.index_records());
+        match self.index.error(self.index_records()) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that `pos` is inside of the chunk section.
+    pub(crate) fn check_chunk_pos(&self, pos: u64) -> Result<()> {
+        match pos < self.start_pos || pos >= self.index_pos {
+            true => Err(Error::new(ErrorKind::OutOfBounds).at(pos)),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn index_records(&self) -> IndexRecordsIterator<'_> {
+        IndexRecordsIterator {
+            cursor: Cursor::new(&self.data),
+            offset: self.index_pos,
+        }
+    }
+
+    pub(crate) fn connection(&self, id: u32) -> Option<Connection<'_>> {
+        self.index.connection(id)
+    }
+
+    pub(crate) fn connections(&self) -> impl Iterator<Item = Connection<'_>> {
+        self.index.connections()
+    }
+
+    pub(crate) fn chunk_infos(&self) -> impl Iterator<Item = ChunkInfo<'_>> {
+        self.index.chunk_infos()
+    }
+
+    pub(crate) fn message_count(&self, conn_id: u32) -> u64 {
+        self.index.message_count(conn_id)
+    }
+}
+
+/// Decompress `Chunk` record including its length prefixes.
+pub(crate) fn decode_chunk(record: &[u8]) -> Result<Chunk<'static>> {
+    let chunk = RawChunk::read(&mut Cursor::new(record)).and_then(|chunk| chunk.decompress())?;
+    Ok(Chunk {
+        compression: chunk.compression,
+        data: ChunkBytes::Shared(chunk.data.into_shared()),
+    })
+}
+
+impl<R: Read + Seek> BagReader<R> {
+    /// Read bag header and index section from `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut version = [0u8; VERSION_LEN as usize];
+        reader.read_exact(&mut version)?;
+        LoadedIndex::check_version(&version)?;
+
+        let record = read_record(&mut reader, len, VERSION_LEN)?;
+        let mut index = LoadedIndex::new(len, &record)?;
+        reader.seek(SeekFrom::Start(index.index_pos))?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        index.set_data(data)?;
+        Ok(Self { reader, index })
+    }
+
+    /// Read and decompress `Chunk` record which starts at `pos`.
+    ///
+    /// Positions of chunks are stored in `ChunkInfo` records, see
+    /// [`BagReader::chunk_infos`].
+    pub fn read_chunk(&mut self, pos: u64) -> Result<Chunk<'static>> {
+        self.index.check_chunk_pos(pos)?;
+        let record = read_record(&mut self.reader, self.index.len, pos)?;
+        decode_chunk(&record).map_err(|e| e.at(pos))
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Read record which starts at `pos` including its length prefixes.
+fn read_record<R: Read + Seek>(reader: &mut R, len: u64, pos: u64) -> Result<Vec<u8>> {
+    let at = |e: io::Error| Error::from(e).at(pos);
+    reader.seek(SeekFrom::Start(pos)).map_err(at)?;
+    let mut buf = Vec::new();
+    // header and data
+    for _ in 0..2 {
+        let mut n = [0u8; 4];
+        reader.read_exact(&mut n).map_err(at)?;
+        buf.extend_from_slice(&n);
+        let n = u32::from_le_bytes(n);
+        check_record_len(pos, buf.len(), n, len)?;
+        let start = buf.len();
+        buf.resize(start + n as usize, 0);
+        reader.read_exact(&mut buf[start..]).map_err(at)?;
+    }
+    Ok(buf)
+}
+
+impl<R> BagReader<R> {
+    /// Get connection count in this rosbag file.
+    pub fn get_conn_count(&self) -> u32 {
+        self.index.conn_count
+    }
+
+    /// Get chunk count in this rosbag file.
+    pub fn get_chunk_count(&self) -> u32 {
+        self.index.chunk_count
+    }
+
+    /// Get iterator over records in the index section.
+    pub fn index_records(&self) -> IndexRecordsIterator<'_> {
+        self.index.index_records()
+    }
+
+    /// Get connection with the given ID.
+    pub fn connection(&self, id: u32) -> Option<Connection<'_>> {
+        self.index.connection(id)
+    }
+
+    /// Get iterator over all connections in the bag sorted by their IDs.
+    pub fn connections(&self) -> impl Iterator<Item = Connection<'_>> {
+        self.index.connections()
+    }
+
+    /// Get iterator over `ChunkInfo` records in the index section.
+    pub fn chunk_infos(&self) -> impl Iterator<Item = ChunkInfo<'_>> {
+        self.index.chunk_infos()
+    }
+
+    /// Get number of messages which arrived on connection with the given ID.
+    pub fn message_count(&self, conn_id: u32) -> u64 {
+        self.index.message_count(conn_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{BagReader, BagStream, ErrorKind};
+    use std::io::Cursor;
+
+    #[test]
+    fn error_offsets() {
+        let data = write_bag(Compression::Lz4, 4, 4);
+        let mut reader = BagReader::new(Cursor::new(&data)).unwrap();
+        let pos = reader
+            .chunk_infos()
+            .map(|info| info.chunk_pos)
+            .max()
+            .unwrap();
+        reader.read_chunk(pos).unwrap();
+        // outside of the chunk section and in the middle of a record
+        for pos in [1, pos + 1] {
+            let err = reader.read_chunk(pos).unwrap_err();
+            assert_eq!(err.kind(), &ErrorKind::OutOfBounds);
+            assert_eq!(err.offset(), Some(pos));
+        }
+
+        // truncate the second chunk
+        let truncated = &data[..pos as usize + 100];
+        let chunks: Vec<_> = BagStream::new(truncated).unwrap().collect();
+        assert_eq!(chunks.len(), 2);
+        let err = chunks[1].as_ref().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Io);
+        assert_eq!(err.offset(), Some(pos));
+    }
+}
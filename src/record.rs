@@ -1,4 +1,4 @@
-use super::{Error, Result};
+use super::{Error, ErrorKind, Result};
 
 use crate::cursor::Cursor;
 
@@ -19,26 +19,13 @@ impl<'a> Record<'a> {
     pub(crate) fn next_record(c: &mut Cursor<'a>) -> Result<Self> {
         let header = c.next_chunk()?;
 
-        let mut op = None;
-        for item in FieldIterator::new(header) {
-            let (name, val) = item?;
-            if name == "op" {
-                if val.len() == 1 {
-                    op = Some(val[0]);
-                    break;
-                } else {
-                    return Err(Error::InvalidRecord);
-                }
-            }
-        }
-
-        Ok(match op {
+        Ok(match read_op(header)? {
             Some(IndexData::OP) => Record::IndexData(IndexData::read(header, c)?),
             Some(Chunk::OP) => Record::Chunk(Chunk::read(header, c)?),
             Some(ChunkInfo::OP) => Record::ChunkInfo(ChunkInfo::read(header, c)?),
             Some(Connection::OP) => Record::Connection(Connection::read(header, c)?),
             Some(MessageData::OP) => Record::MessageData(MessageData::read(header, c)?),
-            _ => return Err(Error::InvalidRecord),
+            _ => return Err(Error::new(ErrorKind::InvalidRecord)),
         })
     }
 
@@ -53,3 +40,18 @@ impl<'a> Record<'a> {
         }
     }
 }
+
+/// Get value of the `op` field from a record header.
+pub(crate) fn read_op(header: &[u8]) -> Result<Option<u8>> {
+    for item in FieldIterator::new(header) {
+        let (name, val) = item?;
+        if name == "op" {
+            if val.len() == 1 {
+                return Ok(Some(val[0]));
+            } else {
+                return Err(Error::new(ErrorKind::InvalidRecord));
+            }
+        }
+    }
+    Ok(None)
+}
@@ -1,12 +1,20 @@
 use super::utils::{set_field_u32, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{Error, ErrorKind, HeaderGen, RecordGen, Result};
 use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::sync::Arc;
 
+use crate::codec::{self, Codec};
 use crate::cursor::Cursor;
-use crate::msg_iter::MessageRecordsIterator;
+use crate::msg_iter::{MessageRecordsIterator, OwnedMessageRecordsIterator};
 
 /// Compression options for `Chunk` data.
-#[derive(Debug, Clone, Copy)]
+///
+/// Compressions are compared by their names.
+#[derive(Clone, Copy)]
 #[non_exhaustive]
 pub enum Compression {
     /// Bzip2 compression.
@@ -15,9 +23,72 @@ pub enum Compression {
     Lz4,
     /// No compression.
     None,
+    /// Compression by a codec from the [`codec`][crate::codec] module.
+    Other(&'static dyn Codec),
 }
 
 impl Compression {
+    /// Value of the `compression` field in the chunk header.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Compression::Bzip2 => "bz2",
+            Compression::Lz4 => "lz4",
+            Compression::None => "none",
+            Compression::Other(codec) => codec.name(),
+        }
+    }
+
+    /// Get compression by value of the `compression` field in the chunk
+    /// header.
+    ///
+    /// Returns `None` if `name` is neither built-in compression nor name of
+    /// a codec available in the [`codec`][crate::codec] module.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "none" => Compression::None,
+            "bz2" => Compression::Bzip2,
+            "lz4" => Compression::Lz4,
+            _ => Compression::Other(codec::find(name)?),
+        })
+    }
+
+    pub(crate) fn compress(self, data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+        Ok(match self {
+            Compression::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+                encoder.write_all(data)?;
+                Cow::from(encoder.finish()?)
+            }
+            Compression::Lz4 => {
+                // roslz4 accepts only frames with independent blocks and
+                // content checksum, without block checksums and content size
+                let mut encoder = lz4::EncoderBuilder::new()
+                    .block_size(lz4::BlockSize::Max4MB)
+                    .block_mode(lz4::BlockMode::Independent)
+                    .block_checksum(lz4::liblz4::BlockChecksum::NoBlockChecksum)
+                    .checksum(lz4::ContentChecksum::ChecksumEnabled)
+                    .build(Vec::new())?;
+                encoder.write_all(data)?;
+                let (compressed, res) = encoder.finish();
+                res?;
+                Cow::from(compressed)
+            }
+            Compression::None => Cow::from(data),
+            Compression::Other(codec) => Cow::from(codec.compress(data)?),
+        })
+    }
+
+    /// Create decompression error caused by `source`.
+    fn error<E>(self, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::with_source(ErrorKind::Decompression(self), source).in_record(ChunkHeader::NAME)
+    }
+
+    // the lint is not known to the MSRV toolchain
+    #[allow(unknown_lints, clippy::reserve_after_initialization)]
     fn decompress(self, data: &[u8], decompressed_size: Option<u32>) -> Result<Cow<'_, [u8]>> {
         Ok(match self {
             Compression::Bzip2 => {
@@ -26,22 +97,221 @@ impl Compression {
                 let mut decompressor = bzip2::Decompress::new(false);
                 decompressor
                     .decompress_vec(data, &mut decompressed)
-                    .map_err(|e| Error::Bzip2DecompressionError(e.to_string()))?;
+                    .map_err(|e| self.error(e))?;
                 Cow::from(decompressed)
             }
             Compression::Lz4 => {
-                let mut decoder = lz4::Decoder::new(data)
-                    .map_err(|e| Error::Lz4DecompressionError(e.to_string()))?;
+                let mut decoder = lz4::Decoder::new(data).map_err(|e| self.error(e))?;
                 let mut decompressed = Vec::new();
                 decompressed.reserve(decompressed_size.map(|s| s as usize).unwrap_or(data.len()));
-                std::io::copy(&mut decoder, &mut decompressed).map_err(|_| {
-                    Error::Lz4DecompressionError("Error while decoding".to_string())
-                })?;
+                std::io::copy(&mut decoder, &mut decompressed).map_err(|e| self.error(e))?;
                 Cow::from(decompressed)
             }
             Compression::None => Cow::from(data),
+            Compression::Other(codec) => {
+                let size = decompressed_size.map(|s| s as usize).unwrap_or(data.len());
+                Cow::from(codec.decompress(data, size).map_err(|e| self.error(e))?)
+            }
         })
     }
+
+    /// Decompress as much data as possible, returning the first encountered
+    /// error together with the data decompressed before it.
+    fn decompress_partial(self, data: &[u8], size: u32) -> (Cow<'_, [u8]>, Option<Error>) {
+        match self {
+            Compression::Bzip2 => {
+                let size = size as usize;
+                let mut decompressed = Vec::with_capacity(codec::preallocation(size, data.len()));
+                let mut decompressor = bzip2::Decompress::new(false);
+                // output is written only into the spare capacity, so grow it
+                // until the end of stream or the declared size is reached
+                let err = loop {
+                    let input = &data[decompressor.total_in() as usize..];
+                    match decompressor.decompress_vec(input, &mut decompressed) {
+                        Ok(bzip2::Status::StreamEnd) => break None,
+                        Ok(_) if decompressed.len() < decompressed.capacity() => break None,
+                        Ok(_) if decompressed.len() >= size => break None,
+                        Ok(_) => {
+                            let len = decompressed.len();
+                            decompressed.reserve_exact(len.max(1).min(size - len));
+                        }
+                        Err(e) => break Some(self.error(e)),
+                    }
+                };
+                (Cow::from(decompressed), err)
+            }
+            // liblz4 does not output blocks which can not be fully decoded
+            Compression::Lz4 => match self.decompress(data, Some(size)) {
+                Ok(decompressed) if decompressed.len() == size as usize => (decompressed, None),
+                Ok(_) => (Cow::from(lz4_salvage(data, size)), None),
+                Err(err) => (Cow::from(lz4_salvage(data, size)), Some(err)),
+            },
+            Compression::None => (Cow::from(data), None),
+            // codecs do not expose partially decompressed data
+            Compression::Other(_) => match self.decompress(data, Some(size)) {
+                Ok(decompressed) => (decompressed, None),
+                Err(err) => (Cow::from(Vec::new()), Some(err)),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Bzip2 => f.write_str("Bzip2"),
+            Compression::Lz4 => f.write_str("Lz4"),
+            Compression::None => f.write_str("None"),
+            Compression::Other(codec) => f.debug_tuple("Other").field(&codec.name()).finish(),
+        }
+    }
+}
+
+impl PartialEq for Compression {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Compression {}
+
+impl Hash for Compression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+/// Decode LZ4 frame up to the first invalid or truncated sequence.
+///
+/// Checksums are ignored, so corrupted data may get decoded into garbage
+/// before an invalid sequence is encountered.
+fn lz4_salvage(data: &[u8], size: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(codec::preallocation(size as usize, data.len()));
+    let mut c = Cursor::new(data);
+    let _ = lz4_decode_frame(&mut c, &mut out, size as usize);
+    out.truncate(size as usize);
+    out
+}
+
+fn lz4_decode_frame(c: &mut Cursor<'_>, out: &mut Vec<u8>, limit: usize) -> Option<()> {
+    if c.next_u32().ok()? != 0x184D_2204 {
+        return None;
+    }
+    let flags = c.next_bytes(2).ok()?[0];
+    let block_checksum = flags & 0x10 != 0;
+    // content size, dictionary ID and header checksum
+    let skip = 8 * u64::from(flags & 0x08 != 0) + 4 * u64::from(flags & 0x01 != 0) + 1;
+    c.next_bytes(skip).ok()?;
+    loop {
+        let len = c.next_u32().ok()?;
+        if len == 0 {
+            return Some(());
+        }
+        let block = c.next_bytes((len & 0x7FFF_FFFF) as u64);
+        let block = match block {
+            Ok(block) => block,
+            // decode available part of the truncated block
+            Err(_) => c.next_bytes(c.left()).ok()?,
+        };
+        if out.len() >= limit {
+            return None;
+        } else if len & 0x8000_0000 != 0 {
+            out.extend_from_slice(block);
+        } else {
+            lz4_decode_block(block, out, limit)?;
+        }
+        if block_checksum {
+            c.next_u32().ok()?;
+        }
+    }
+}
+
+fn lz4_decode_block(block: &[u8], out: &mut Vec<u8>, limit: usize) -> Option<()> {
+    let read_len = |pos: &mut usize, mut len: usize| -> Option<usize> {
+        if len == 15 {
+            loop {
+                let b = *block.get(*pos)?;
+                *pos += 1;
+                len += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    };
+    let mut pos = 0;
+    while pos < block.len() {
+        let token = block[pos];
+        pos += 1;
+        let len = read_len(&mut pos, (token >> 4) as usize)?;
+        let literals = block.get(pos..pos + len);
+        let literals = literals.unwrap_or(&block[pos..]);
+        out.extend_from_slice(literals);
+        pos += literals.len();
+        if pos + 2 > block.len() {
+            // the last sequence contains only literals
+            return if literals.len() == len {
+                Some(())
+            } else {
+                None
+            };
+        }
+        let offset = u16::from_le_bytes([block[pos], block[pos + 1]]) as usize;
+        pos += 2;
+        let len = read_len(&mut pos, (token & 0x0F) as usize)? + 4;
+        if offset == 0 || offset > out.len() || out.len() + len > limit {
+            return None;
+        }
+        let start = out.len() - offset;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+    Some(())
+}
+
+/// Buffer with decompressed chunk data.
+#[derive(Debug, Clone)]
+pub(crate) enum ChunkBytes<'a> {
+    /// Data of uncompressed chunk stored in the bag
+    Borrowed(&'a [u8]),
+    /// Decompressed chunk data shared between messages and the chunk cache
+    Shared(Arc<Vec<u8>>),
+}
+
+impl<'a> ChunkBytes<'a> {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        match self {
+            ChunkBytes::Borrowed(data) => data,
+            ChunkBytes::Shared(data) => data,
+        }
+    }
+
+    /// Convert into shared buffer, copying borrowed data.
+    pub(crate) fn into_shared(self) -> Arc<Vec<u8>> {
+        match self {
+            ChunkBytes::Borrowed(data) => Arc::new(data.to_vec()),
+            ChunkBytes::Shared(data) => data,
+        }
+    }
+}
+
+impl<'a> Deref for ChunkBytes<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<'a> From<Cow<'a, [u8]>> for ChunkBytes<'a> {
+    fn from(data: Cow<'a, [u8]>) -> Self {
+        match data {
+            Cow::Borrowed(data) => ChunkBytes::Borrowed(data),
+            Cow::Owned(data) => ChunkBytes::Shared(Arc::new(data)),
+        }
+    }
 }
 
 /// Bulk storage with optional compression for messages data and connection
@@ -51,7 +321,7 @@ pub struct Chunk<'a> {
     /// Compression type for the data
     pub compression: Compression,
     /// Decompressed messages data and connection records
-    data: Cow<'a, [u8]>,
+    pub(crate) data: ChunkBytes<'a>,
 }
 
 impl<'a> Chunk<'a> {
@@ -59,6 +329,73 @@ impl<'a> Chunk<'a> {
     pub fn messages(&self) -> MessageRecordsIterator<'_> {
         MessageRecordsIterator::new(&self.data)
     }
+
+    /// Get iterator over records which share data of the chunk and do not
+    /// borrow from it.
+    ///
+    /// Data of uncompressed chunks borrowed from the bag gets copied once.
+    pub fn owned_messages(&self) -> OwnedMessageRecordsIterator {
+        OwnedMessageRecordsIterator::new(self.data.clone().into_shared())
+    }
+
+    /// Convert into chunk which owns its data.
+    ///
+    /// Decompressed data is shared without copying, while data of
+    /// uncompressed chunks borrowed from the bag gets copied.
+    pub fn into_owned(self) -> Chunk<'static> {
+        Chunk {
+            compression: self.compression,
+            data: ChunkBytes::Shared(self.data.into_shared()),
+        }
+    }
+}
+
+impl Chunk<'static> {
+    /// Read data of `Chunk` record with the given header from `reader`
+    /// decompressing it on the fly.
+    ///
+    /// `reader` should be limited to the record data, data left in it after
+    /// the end of compressed stream is not consumed.
+    pub(crate) fn read_from<R: Read>(header: &[u8], mut reader: R) -> Result<Self> {
+        let header = ChunkHeader::read_header(header)?;
+        let compression = ChunkHeader::required(header.compression, "compression")?;
+        let size = ChunkHeader::required(header.size, "size")?;
+
+        // read one extra byte to detect data longer than declared
+        let limit = size as u64 + 1;
+        let mut data = Vec::with_capacity(size as usize);
+        match compression {
+            Compression::Bzip2 => {
+                let decoder = bzip2::read::BzDecoder::new(reader);
+                decoder.take(limit).read_to_end(&mut data)?
+            }
+            Compression::Lz4 => lz4::Decoder::new(reader)?
+                .take(limit)
+                .read_to_end(&mut data)?,
+            Compression::None => reader.take(limit).read_to_end(&mut data)?,
+            Compression::Other(codec) => {
+                let mut compressed = Vec::new();
+                reader.read_to_end(&mut compressed)?;
+                data = codec
+                    .decompress(&compressed, size as usize)
+                    .map_err(|e| compression.error(e))?;
+                data.len()
+            }
+        };
+        if data.len() != size as usize {
+            return Err(size_mismatch());
+        }
+        Ok(Chunk {
+            compression,
+            data: ChunkBytes::Shared(Arc::new(data)),
+        })
+    }
+}
+
+/// Error for decompressed data which size does not match the chunk header.
+fn size_mismatch() -> Error {
+    let err = Error::new(ErrorKind::InvalidRecord).in_field("size");
+    ChunkHeader::context(err)
 }
 
 #[derive(Debug, Clone, Default)]
@@ -71,31 +408,125 @@ impl<'a> RecordGen<'a> for Chunk<'a> {
     type Header = ChunkHeader;
 
     fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
-        let compression = header.compression.ok_or(Error::InvalidHeader)?;
-        let size = header.size.ok_or(Error::InvalidHeader)?;
-        let data = compression.decompress(c.next_chunk()?, header.size)?;
-        if data.len() != size as usize {
-            return Err(Error::InvalidRecord);
+        RawChunk::read_data(c, header)?.decompress()
+    }
+}
+
+/// `Chunk` record with not yet decompressed data.
+#[derive(Debug, Clone)]
+pub(crate) struct RawChunk<'a> {
+    pub compression: Compression,
+    /// Size of the decompressed data
+    pub size: u32,
+    /// Compressed data
+    pub data: &'a [u8],
+}
+
+impl<'a> RawChunk<'a> {
+    /// Read `Chunk` record starting at the current cursor position.
+    pub(crate) fn read(c: &mut Cursor<'a>) -> Result<Self> {
+        let header = c.next_chunk()?;
+        Self::read_with_header(header, c)
+    }
+
+    /// Read `Chunk` record which header was already read from the cursor.
+    pub(crate) fn read_with_header(header: &'a [u8], c: &mut Cursor<'a>) -> Result<Self> {
+        Self::read_data(c, ChunkHeader::read_header(header)?)
+    }
+
+    /// Same as [`RawChunk::read_with_header`], but if the record is truncated
+    /// takes all data left in the cursor. Returns `true` together with the
+    /// chunk if the record was truncated.
+    pub(crate) fn read_truncated(header: &'a [u8], c: &mut Cursor<'a>) -> Result<(Self, bool)> {
+        let header = ChunkHeader::read_header(header)?;
+        let compression = ChunkHeader::required(header.compression, "compression")?;
+        let size = ChunkHeader::required(header.size, "size")?;
+        let len = c.next_u32()? as u64;
+        let truncated = len > c.left();
+        let data = c.next_bytes(len.min(c.left()))?;
+        let chunk = Self {
+            compression,
+            size,
+            data,
+        };
+        Ok((chunk, truncated))
+    }
+
+    fn read_data(c: &mut Cursor<'a>, header: ChunkHeader) -> Result<Self> {
+        let compression = ChunkHeader::required(header.compression, "compression")?;
+        let size = ChunkHeader::required(header.size, "size")?;
+        let data = c.next_chunk()?;
+        Ok(Self {
+            compression,
+            size,
+            data,
+        })
+    }
+
+    /// Salvage chunk data up to the point of corruption.
+    ///
+    /// Returns chunk which data is truncated to the last complete record
+    /// before the first decompression or parsing error, together with that
+    /// error. `data` may be shorter than declared by the chunk header if the
+    /// record is truncated.
+    pub(crate) fn decompress_partial(self) -> (Chunk<'a>, Option<Error>) {
+        let (data, mut err) = self.compression.decompress_partial(self.data, self.size);
+        if err.is_none() && data.len() != self.size as usize {
+            err = Some(size_mismatch());
         }
-        Ok(Self { compression, data })
+
+        let mut records = MessageRecordsIterator::new(&data);
+        let mut end = 0;
+        while let Some(res) = records.next() {
+            match res {
+                Ok(_) => end = records.cursor.pos() as usize,
+                Err(e) => {
+                    err = err.or(Some(e));
+                    break;
+                }
+            }
+        }
+        let data = match data {
+            Cow::Borrowed(data) => ChunkBytes::Borrowed(&data[..end]),
+            Cow::Owned(mut data) => {
+                data.truncate(end);
+                ChunkBytes::Shared(Arc::new(data))
+            }
+        };
+        let chunk = Chunk {
+            compression: self.compression,
+            data,
+        };
+        (chunk, err)
+    }
+
+    pub(crate) fn decompress(self) -> Result<Chunk<'a>> {
+        let data = self.compression.decompress(self.data, Some(self.size))?;
+        if data.len() != self.size as usize {
+            return Err(size_mismatch());
+        }
+        Ok(Chunk {
+            compression: self.compression,
+            data: data.into(),
+        })
     }
 }
 
 impl<'a> HeaderGen<'a> for ChunkHeader {
     const OP: u8 = 0x05;
+    const NAME: &'static str = "Chunk";
 
     fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
         match name {
             "compression" => {
                 if self.compression.is_some() {
-                    return Err(Error::InvalidHeader);
+                    return Err(Error::new(ErrorKind::InvalidHeader));
                 }
-                self.compression = Some(match val {
-                    b"none" => Compression::None,
-                    b"bz2" => Compression::Bzip2,
-                    b"lz4" => Compression::Lz4,
-                    _ => return Err(Error::InvalidHeader),
-                });
+                let name = String::from_utf8_lossy(val);
+                let compression = Compression::from_name(&name).ok_or_else(|| {
+                    Error::new(ErrorKind::UnsupportedCompression(name.into_owned()))
+                })?;
+                self.compression = Some(compression);
             }
             "size" => set_field_u32(&mut self.size, val)?,
             _ => unknown_field(name, val),
@@ -103,3 +534,128 @@ impl<'a> HeaderGen<'a> for ChunkHeader {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{lz4_decode_block, lz4_salvage, Compression};
+
+    /// Block with literals `abcd`, 8 bytes long match at offset 4 and the
+    /// last literal `e`.
+    const BLOCK: &[u8] = b"\x44abcd\x04\x00\x10e";
+
+    /// Build LZ4 frame with the given flags byte and blocks data.
+    fn frame(flags: u8, blocks: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x04, 0x22, 0x4D, 0x18, flags, 0x70];
+        if flags & 0x08 != 0 {
+            frame.extend_from_slice(&13u64.to_le_bytes());
+        }
+        // header checksum is ignored
+        frame.push(0);
+        frame.extend_from_slice(blocks);
+        frame.extend_from_slice(&[0; 4]);
+        frame
+    }
+
+    #[test]
+    fn lz4_block() {
+        let mut out = Vec::new();
+        assert_eq!(lz4_decode_block(BLOCK, &mut out, 100), Some(()));
+        assert_eq!(out, b"abcdabcdabcde");
+
+        // match which does not fit into the limit
+        let mut out = Vec::new();
+        assert_eq!(lz4_decode_block(BLOCK, &mut out, 10), None);
+        assert_eq!(out, b"abcd");
+    }
+
+    #[test]
+    fn lz4_invalid_offsets() {
+        for offset in [0, 5] {
+            let block = [&b"\x44abcd"[..], &[offset, 0], b"\x10e"].concat();
+            let mut out = Vec::new();
+            assert_eq!(lz4_decode_block(&block, &mut out, 100), None);
+            assert_eq!(out, b"abcd");
+        }
+
+        // offsets may point into data of the previous blocks, but not
+        // before the start of the output
+        let block = b"\x44abcd\x06\x00\x10e";
+        let mut out = b"xy".to_vec();
+        assert_eq!(lz4_decode_block(block, &mut out, 100), Some(()));
+        assert_eq!(out, b"xyabcdxyabcdxye");
+        let block = b"\x44abcd\x07\x00\x10e";
+        let mut out = b"xy".to_vec();
+        assert_eq!(lz4_decode_block(block, &mut out, 100), None);
+        assert_eq!(out, b"xyabcd");
+    }
+
+    #[test]
+    fn lz4_oversized_literals() {
+        // 15 + 255 + 16 literals declared, but only 3 are present
+        let mut out = Vec::new();
+        assert_eq!(lz4_decode_block(b"\xF0\xFF\x10abc", &mut out, 100), None);
+        assert_eq!(out, b"abc");
+        // truncated literal length
+        let mut out = Vec::new();
+        assert_eq!(lz4_decode_block(b"\xF0\xFF", &mut out, 100), None);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn lz4_frame_flags() {
+        // uncompressed block `xy` and `BLOCK`, each followed by a zero checksum
+        let mut blocks = Vec::new();
+        blocks.extend_from_slice(&0x8000_0002u32.to_le_bytes());
+        blocks.extend_from_slice(b"xy\0\0\0\0");
+        blocks.extend_from_slice(&(BLOCK.len() as u32).to_le_bytes());
+        blocks.extend_from_slice(BLOCK);
+        blocks.extend_from_slice(&[0; 4]);
+
+        let expected = b"xyabcdabcdabcde";
+        assert_eq!(lz4_salvage(&frame(0x50, &blocks), 100), expected);
+        // content size
+        assert_eq!(lz4_salvage(&frame(0x58, &blocks), 100), expected);
+        // match which does not fit into the declared size is dropped
+        assert_eq!(lz4_salvage(&frame(0x50, &blocks), 7), b"xyabcd");
+        // without block checksums the first checksum is read as the end mark
+        assert_eq!(lz4_salvage(&frame(0x40, &blocks), 100), b"xy");
+        // invalid magic
+        assert!(lz4_salvage(&frame(0x50, &blocks)[1..], 100).is_empty());
+    }
+
+    #[test]
+    fn lz4_truncated_frame() {
+        // text of random words, so the compressed data has many sequences
+        let mut state = 1u32;
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b"abcdefgh "[(state >> 16) as usize % 9]
+            })
+            .collect();
+        let compressed = Compression::Lz4.compress(&data).unwrap();
+        let truncated = &compressed[..compressed.len() / 2];
+        let (out, err) = Compression::Lz4.decompress_partial(truncated, data.len() as u32);
+        // truncation is detected by comparing the size with the chunk header
+        assert!(err.is_none());
+        assert!(out.len() > data.len() / 4, "{}", out.len());
+        assert!(data.starts_with(&out));
+    }
+
+    #[test]
+    fn bzip2_partial() {
+        // compression ratio is much higher than the preallocated capacity
+        let data = vec![0; 1 << 20];
+        let compressed = Compression::Bzip2.compress(&data).unwrap();
+        let (out, err) = Compression::Bzip2.decompress_partial(&compressed, 1 << 20);
+        assert!(err.is_none());
+        assert_eq!(out, data);
+        // declared size is not used for preallocation
+        let (out, err) = Compression::Bzip2.decompress_partial(&compressed, u32::MAX);
+        assert!(err.is_none());
+        assert_eq!(out.len(), data.len());
+        // output is limited by the declared size
+        let (out, _) = Compression::Bzip2.decompress_partial(&compressed, 1000);
+        assert_eq!(out.len(), 1000);
+    }
+}
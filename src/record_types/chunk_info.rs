@@ -1,7 +1,9 @@
 use super::utils::{set_field_time, set_field_u32, set_field_u64, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{Error, ErrorKind, HeaderGen, RecordGen, Result};
 
 use crate::cursor::Cursor;
+use crate::time::Time;
+use std::borrow::Cow;
 
 /// High-level index of `Chunk` records.
 #[derive(Debug, Clone)]
@@ -10,19 +12,38 @@ pub struct ChunkInfo<'a> {
     pub ver: u32,
     /// Offset of the chunk record relative to the bag file beginning
     pub chunk_pos: u64,
-    /// Timestamp of earliest message in the chunk in nanoseconds of UNIX epoch
-    pub start_time: u64,
-    /// Timestamp of latest message in the chunk in nanoseconds of UNIX epoch
-    pub end_time: u64,
+    /// Timestamp of earliest message in the chunk
+    pub start_time: Time,
+    /// Timestamp of latest message in the chunk
+    pub end_time: Time,
     /// Index entries data
-    data: &'a [u8],
+    data: Cow<'a, [u8]>,
 }
 
 impl<'a> ChunkInfo<'a> {
     /// Get entries iterator.
     pub fn entries(&'a self) -> ChunkInfoEntriesIterator<'a> {
         ChunkInfoEntriesIterator {
-            cursor: Cursor::new(self.data),
+            cursor: Cursor::new(&self.data),
+        }
+    }
+
+    /// Get record which borrows data of this one.
+    pub(crate) fn as_borrowed(&self) -> ChunkInfo<'_> {
+        ChunkInfo {
+            data: Cow::Borrowed(&self.data),
+            ..*self
+        }
+    }
+
+    /// Convert into record which owns its data.
+    pub fn into_owned(self) -> ChunkInfo<'static> {
+        ChunkInfo {
+            ver: self.ver,
+            chunk_pos: self.chunk_pos,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            data: Cow::Owned(self.data.into_owned()),
         }
     }
 }
@@ -31,8 +52,8 @@ impl<'a> ChunkInfo<'a> {
 pub(crate) struct ChunkInfoHeader {
     pub ver: Option<u32>,
     pub chunk_pos: Option<u64>,
-    pub start_time: Option<u64>,
-    pub end_time: Option<u64>,
+    pub start_time: Option<Time>,
+    pub end_time: Option<Time>,
     pub count: Option<u32>,
 }
 
@@ -40,18 +61,18 @@ impl<'a> RecordGen<'a> for ChunkInfo<'a> {
     type Header = ChunkInfoHeader;
 
     fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
-        let ver = header.ver.ok_or(Error::InvalidHeader)?;
-        let chunk_pos = header.chunk_pos.ok_or(Error::InvalidHeader)?;
-        let start_time = header.start_time.ok_or(Error::InvalidHeader)?;
-        let end_time = header.end_time.ok_or(Error::InvalidHeader)?;
-        let count = header.count.ok_or(Error::InvalidHeader)?;
+        let ver = Self::Header::required(header.ver, "ver")?;
+        let chunk_pos = Self::Header::required(header.chunk_pos, "chunk_pos")?;
+        let start_time = Self::Header::required(header.start_time, "start_time")?;
+        let end_time = Self::Header::required(header.end_time, "end_time")?;
+        let count = Self::Header::required(header.count, "count")?;
 
         if ver != 1 {
-            return Err(Error::UnsupportedVersion);
+            return Err(Error::new(ErrorKind::UnsupportedVersion).in_field("ver"));
         }
         let n = c.next_u32()?;
         if n % 8 != 0 || n / 8 != count {
-            return Err(Error::InvalidRecord);
+            return Err(Error::new(ErrorKind::InvalidRecord).in_field("count"));
         }
         let data = c.next_bytes(n as u64)?;
         Ok(Self {
@@ -59,13 +80,14 @@ impl<'a> RecordGen<'a> for ChunkInfo<'a> {
             chunk_pos,
             start_time,
             end_time,
-            data,
+            data: Cow::Borrowed(data),
         })
     }
 }
 
 impl<'a> HeaderGen<'a> for ChunkInfoHeader {
     const OP: u8 = 0x06;
+    const NAME: &'static str = "ChunkInfo";
 
     fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
         match name {
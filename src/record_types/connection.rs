@@ -1,10 +1,11 @@
 use super::utils::{check_op, read_record, unknown_field};
 use super::utils::{set_field_str, set_field_u32};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{Error, ErrorKind, HeaderGen, RecordGen, Result};
 use log::warn;
 
 use crate::cursor::Cursor;
 use crate::field_iter::FieldIterator;
+use crate::schema::MessageSchema;
 
 /// Connection record which contains message type for ROS topic.
 ///
@@ -33,6 +34,81 @@ pub struct Connection<'a> {
     pub latching: bool,
 }
 
+impl<'a> Connection<'a> {
+    /// Convert into record which owns copies of its strings.
+    pub fn into_owned(self) -> OwnedConnection {
+        OwnedConnection {
+            id: self.id,
+            storage_topic: self.storage_topic.to_string(),
+            topic: self.topic.to_string(),
+            tp: self.tp.to_string(),
+            md5sum: self.md5sum,
+            message_definition: self.message_definition.to_string(),
+            caller_id: self.caller_id.to_string(),
+            latching: self.latching,
+        }
+    }
+
+    /// Parse message definition of the connection message type.
+    pub fn message_schema(&self) -> Result<MessageSchema> {
+        MessageSchema::parse(self.tp, self.message_definition)
+    }
+
+    /// Compute MD5 sum of the message type from its definition.
+    pub fn compute_md5sum(&self) -> Result<[u8; 16]> {
+        self.message_schema()?.md5sum()
+    }
+}
+
+/// Connection record which does not borrow from the bag or the chunk.
+///
+/// See [`Connection`] for description of the fields.
+#[derive(Debug, Clone)]
+pub struct OwnedConnection {
+    /// Unique connection ID
+    pub id: u32,
+    /// Topic on which the messages are stored
+    pub storage_topic: String,
+    /// Name of the topic the subscriber is connecting to
+    pub topic: String,
+    /// Message type
+    pub tp: String,
+    /// MD5 hash sum of the message type
+    pub md5sum: [u8; 16],
+    /// Full text of the message definition
+    pub message_definition: String,
+    /// Name of node sending data (can be empty)
+    pub caller_id: String,
+    /// Is publisher in the latching mode?
+    pub latching: bool,
+}
+
+impl OwnedConnection {
+    /// Get borrowed record with the same fields.
+    pub fn as_connection(&self) -> Connection<'_> {
+        Connection {
+            id: self.id,
+            storage_topic: &self.storage_topic,
+            topic: &self.topic,
+            tp: &self.tp,
+            md5sum: self.md5sum,
+            message_definition: &self.message_definition,
+            caller_id: &self.caller_id,
+            latching: self.latching,
+        }
+    }
+
+    /// Parse message definition of the connection message type.
+    pub fn message_schema(&self) -> Result<MessageSchema> {
+        self.as_connection().message_schema()
+    }
+
+    /// Compute MD5 sum of the message type from its definition.
+    pub fn compute_md5sum(&self) -> Result<[u8; 16]> {
+        self.as_connection().compute_md5sum()
+    }
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct ConnectionHeader<'a> {
     pub id: Option<u32>,
@@ -43,8 +119,8 @@ impl<'a> RecordGen<'a> for Connection<'a> {
     type Header = ConnectionHeader<'a>;
 
     fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
-        let id = header.id.ok_or(Error::InvalidHeader)?;
-        let storage_topic = header.storage_topic.ok_or(Error::InvalidHeader)?;
+        let id = Self::Header::required(header.id, "conn")?;
+        let storage_topic = Self::Header::required(header.storage_topic, "topic")?;
 
         let buf = c.next_chunk()?;
 
@@ -57,34 +133,31 @@ impl<'a> RecordGen<'a> for Connection<'a> {
 
         for field in FieldIterator::new(buf) {
             let (name, val) = field?;
-            match name {
-                "topic" => set_field_str(&mut topic, val)?,
-                "type" => set_field_str(&mut tp, val)?,
-                "md5sum" => {
-                    if md5sum.is_some() || val.len() != 32 {
-                        return Err(Error::InvalidRecord);
-                    }
-                    let mut res = [0u8; 16];
-                    base16ct::lower::decode(val, &mut res).map_err(|_| Error::InvalidRecord)?;
-                    md5sum = Some(res);
-                }
-                "message_definition" => set_field_str(&mut message_definition, val)?,
-                "callerid" => set_field_str(&mut caller_id, val)?,
-                "latching" => {
-                    latching = match val {
-                        b"1" => true,
-                        b"0" => false,
-                        _ => return Err(Error::InvalidRecord),
+            let res = match name {
+                "topic" => set_field_str(&mut topic, val),
+                "type" => set_field_str(&mut tp, val),
+                "md5sum" => set_field_md5sum(&mut md5sum, val),
+                "message_definition" => set_field_str(&mut message_definition, val),
+                "callerid" => set_field_str(&mut caller_id, val),
+                "latching" => match val {
+                    b"1" | b"0" => {
+                        latching = val == b"1";
+                        Ok(())
                     }
+                    _ => Err(ErrorKind::InvalidRecord.into()),
+                },
+                _ => {
+                    warn!("Unknown field in the connection header: {}", name);
+                    Ok(())
                 }
-                _ => warn!("Unknown field in the connection header: {}", name),
-            }
+            };
+            res.map_err(|e| e.in_field(name))?;
         }
 
-        let topic = topic.ok_or(Error::InvalidHeader)?;
-        let tp = tp.ok_or(Error::InvalidHeader)?;
-        let md5sum = md5sum.ok_or(Error::InvalidHeader)?;
-        let message_definition = message_definition.ok_or(Error::InvalidHeader)?;
+        let topic = Self::Header::required(topic, "topic")?;
+        let tp = Self::Header::required(tp, "type")?;
+        let md5sum = Self::Header::required(md5sum, "md5sum")?;
+        let message_definition = Self::Header::required(message_definition, "message_definition")?;
         let caller_id = caller_id.unwrap_or("");
         Ok(Self {
             id,
@@ -101,17 +174,19 @@ impl<'a> RecordGen<'a> for Connection<'a> {
 
 impl<'a> HeaderGen<'a> for ConnectionHeader<'a> {
     const OP: u8 = 0x07;
+    const NAME: &'static str = "Connection";
 
     fn read_header(mut header: &'a [u8]) -> Result<Self> {
         let mut rec = Self::default();
         while !header.is_empty() {
-            let (name, val, new_header) = read_record(header)?;
+            let (name, val, new_header) = read_record(header).map_err(Self::context)?;
             header = new_header;
-            match name {
-                "op" => check_op(val, Self::OP)?,
-                "topic" => set_field_str(&mut rec.storage_topic, val)?,
-                _ => rec.process_field(name, val)?,
-            }
+            let res = match name {
+                "op" => check_op(val, Self::OP),
+                "topic" => set_field_str(&mut rec.storage_topic, val),
+                _ => rec.process_field(name, val),
+            };
+            res.map_err(|e| Self::context(e.in_field(name)))?;
         }
         Ok(rec)
     }
@@ -124,3 +199,13 @@ impl<'a> HeaderGen<'a> for ConnectionHeader<'a> {
         Ok(())
     }
 }
+
+fn set_field_md5sum(field: &mut Option<[u8; 16]>, val: &[u8]) -> Result<()> {
+    if field.is_some() || val.len() != 32 {
+        return Err(ErrorKind::InvalidRecord.into());
+    }
+    let mut res = [0u8; 16];
+    base16ct::lower::decode(val, &mut res).map_err(|_| Error::new(ErrorKind::InvalidRecord))?;
+    *field = Some(res);
+    Ok(())
+}
@@ -1,7 +1,9 @@
 use super::utils::{set_field_u32, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{Error, ErrorKind, HeaderGen, RecordGen, Result};
 
 use crate::cursor::Cursor;
+use crate::time::Time;
+use std::borrow::Cow;
 
 /// Index record which describes messages offset for `Connection` with
 /// `conn_id` ID in the preceding `Chunk`.
@@ -12,14 +14,23 @@ pub struct IndexData<'a> {
     /// Connection ID
     pub conn_id: u32,
     /// Occurrences of timestamps, chunk record offsets and message offsets
-    data: &'a [u8],
+    data: Cow<'a, [u8]>,
 }
 
 impl<'a> IndexData<'a> {
     /// Get entries iterator.
     pub fn entries(&'a self) -> IndexDataEntriesIterator<'a> {
         IndexDataEntriesIterator {
-            cursor: Cursor::new(self.data),
+            cursor: Cursor::new(&self.data),
+        }
+    }
+
+    /// Convert into record which owns its data.
+    pub fn into_owned(self) -> IndexData<'static> {
+        IndexData {
+            ver: self.ver,
+            conn_id: self.conn_id,
+            data: Cow::Owned(self.data.into_owned()),
         }
     }
 }
@@ -35,24 +46,29 @@ impl<'a> RecordGen<'a> for IndexData<'a> {
     type Header = IndexDataHeader;
 
     fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
-        let ver = header.ver.ok_or(Error::InvalidHeader)?;
-        let conn_id = header.conn_id.ok_or(Error::InvalidHeader)?;
-        let count = header.count.ok_or(Error::InvalidHeader)?;
+        let ver = Self::Header::required(header.ver, "ver")?;
+        let conn_id = Self::Header::required(header.conn_id, "conn")?;
+        let count = Self::Header::required(header.count, "count")?;
 
         if ver != 1 {
-            return Err(Error::UnsupportedVersion);
+            return Err(Error::new(ErrorKind::UnsupportedVersion).in_field("ver"));
         }
         let n = c.next_u32()?;
         if n % 12 != 0 || n / 12 != count {
-            return Err(Error::InvalidRecord);
+            return Err(Error::new(ErrorKind::InvalidRecord).in_field("count"));
         }
         let data = c.next_bytes(n as u64)?;
-        Ok(Self { ver, conn_id, data })
+        Ok(Self {
+            ver,
+            conn_id,
+            data: Cow::Borrowed(data),
+        })
     }
 }
 
 impl<'a> HeaderGen<'a> for IndexDataHeader {
     const OP: u8 = 0x04;
+    const NAME: &'static str = "IndexData";
 
     fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
         match name {
@@ -69,7 +85,7 @@ impl<'a> HeaderGen<'a> for IndexDataHeader {
 #[derive(Debug, Clone, Default)]
 pub struct IndexDataEntry {
     /// Time at which the message was received
-    pub time: u64,
+    pub time: Time,
     /// Offset of message data record in uncompressed chunk data
     pub offset: u32,
 }
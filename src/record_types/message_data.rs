@@ -1,30 +1,111 @@
 use super::utils::{set_field_time, set_field_u32, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{HeaderGen, RecordGen, Result};
 use crate::cursor::Cursor;
+use crate::time::Time;
+use std::sync::Arc;
 
 /// Message data for a `Connection` with `conn_id` ID.
 #[derive(Debug, Clone)]
 pub struct MessageData<'a> {
     /// ID for connection on which message arrived
     pub conn_id: u32,
-    /// Time at which the message was received in nanoseconds of UNIX epoch
-    pub time: u64,
+    /// Time at which the message was received
+    pub time: Time,
     /// Serialized message data in the ROS serialization format
     pub data: &'a [u8],
 }
 
+impl<'a> MessageData<'a> {
+    /// Convert into record which owns a copy of the message data.
+    ///
+    /// Use [`Chunk::owned_messages`][crate::record_types::Chunk::owned_messages]
+    /// to get records which share data of the chunk instead.
+    pub fn into_owned(self) -> OwnedMessageData {
+        let data = Arc::new(self.data.to_vec());
+        let end = data.len();
+        OwnedMessageData::from_shared(self.conn_id, self.time, data, 0, end)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a> MessageData<'a> {
+    /// Deserialize message data into `T`.
+    ///
+    /// See the [`de`][crate::de] module for details of the type mapping.
+    pub fn deserialize<T: serde::Deserialize<'a>>(&self) -> Result<T> {
+        crate::de::from_slice(self.data)
+    }
+}
+
+/// Message data record which does not borrow from the bag or the chunk.
+///
+/// Data of records read by [`Chunk::owned_messages`][crate::record_types::Chunk::owned_messages]
+/// is shared with other records from the same chunk, so cloning
+/// `OwnedMessageData` is cheap.
+#[derive(Debug, Clone)]
+pub struct OwnedMessageData {
+    /// ID for connection on which message arrived
+    pub conn_id: u32,
+    /// Time at which the message was received
+    pub time: Time,
+    chunk: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl OwnedMessageData {
+    /// Create record which data is stored in `chunk[start..end]`.
+    pub(crate) fn from_shared(
+        conn_id: u32,
+        time: Time,
+        chunk: Arc<Vec<u8>>,
+        start: usize,
+        end: usize,
+    ) -> Self {
+        Self {
+            conn_id,
+            time,
+            chunk,
+            start,
+            end,
+        }
+    }
+
+    /// Serialized message data in the ROS serialization format.
+    pub fn data(&self) -> &[u8] {
+        &self.chunk[self.start..self.end]
+    }
+
+    /// Get borrowed record with the same data.
+    pub fn as_message_data(&self) -> MessageData<'_> {
+        MessageData {
+            conn_id: self.conn_id,
+            time: self.time,
+            data: self.data(),
+        }
+    }
+
+    /// Deserialize message data into `T`.
+    ///
+    /// See the [`de`][crate::de] module for details of the type mapping.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<'de, T: serde::Deserialize<'de>>(&'de self) -> Result<T> {
+        crate::de::from_slice(self.data())
+    }
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct MessageDataHeader {
     pub conn_id: Option<u32>,
-    pub time: Option<u64>,
+    pub time: Option<Time>,
 }
 
 impl<'a> RecordGen<'a> for MessageData<'a> {
     type Header = MessageDataHeader;
 
     fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
-        let conn_id = header.conn_id.ok_or(Error::InvalidHeader)?;
-        let time = header.time.ok_or(Error::InvalidHeader)?;
+        let conn_id = Self::Header::required(header.conn_id, "conn")?;
+        let time = Self::Header::required(header.time, "time")?;
         let data = c.next_chunk()?;
         Ok(MessageData {
             conn_id,
@@ -36,6 +117,7 @@ impl<'a> RecordGen<'a> for MessageData<'a> {
 
 impl<'a> HeaderGen<'a> for MessageDataHeader {
     const OP: u8 = 0x02;
+    const NAME: &'static str = "MessageData";
 
     fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
         match name {
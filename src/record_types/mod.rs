@@ -1,14 +1,15 @@
 //! Collection of record types.
-use super::{Error, Result};
+use super::{Error, ErrorKind, Result};
 
 use crate::cursor::Cursor;
 
 mod chunk;
 pub use self::chunk::{Chunk, Compression};
+pub(crate) use self::chunk::{ChunkBytes, RawChunk};
 pub(crate) mod message_data;
-pub use self::message_data::MessageData;
+pub use self::message_data::{MessageData, OwnedMessageData};
 pub(crate) mod connection;
-pub use self::connection::Connection;
+pub use self::connection::{Connection, OwnedConnection};
 mod index_data;
 pub use self::index_data::{IndexData, IndexDataEntriesIterator, IndexDataEntry};
 mod chunk_info;
@@ -19,22 +20,35 @@ use self::utils::{check_op, read_record};
 
 pub(crate) trait HeaderGen<'a>: Sized + Default {
     const OP: u8;
+    /// Record type name used in errors
+    const NAME: &'static str;
 
     fn read_header(mut header: &'a [u8]) -> Result<Self> {
         let mut rec = Self::default();
         while !header.is_empty() {
-            let (name, val, new_header) = read_record(header)?;
+            let (name, val, new_header) = read_record(header).map_err(Self::context)?;
             header = new_header;
-            if name == "op" {
-                check_op(val, Self::OP)?;
+            let res = if name == "op" {
+                check_op(val, Self::OP)
             } else {
-                rec.process_field(name, val)?;
-            }
+                rec.process_field(name, val)
+            };
+            res.map_err(|e| Self::context(e.in_field(name)))?;
         }
         Ok(rec)
     }
 
     fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()>;
+
+    /// Get value of a required header field.
+    fn required<T>(field: Option<T>, name: &str) -> Result<T> {
+        field.ok_or_else(|| Self::context(Error::new(ErrorKind::MissingField).in_field(name)))
+    }
+
+    /// Add record type to the error.
+    fn context(err: Error) -> Error {
+        err.in_record(Self::NAME)
+    }
 }
 
 pub(crate) trait RecordGen<'a>: Sized {
@@ -45,7 +59,7 @@ pub(crate) trait RecordGen<'a>: Sized {
 
     fn read(header: &'a [u8], c: &mut Cursor<'a>) -> Result<Self> {
         let header = Self::Header::read_header(header)?;
-        Self::read_data(c, header)
+        Self::read_data(c, header).map_err(Self::Header::context)
     }
 
     fn read_data(c: &mut Cursor<'a>, h: Self::Header) -> Result<Self>;
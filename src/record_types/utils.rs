@@ -1,16 +1,17 @@
-use super::{Error, Result};
+use super::{Error, ErrorKind, Result};
+use crate::time::Time;
 use byteorder::{ByteOrder, LE};
 use std::str;
 
 pub(crate) fn read_record(mut header: &[u8]) -> Result<(&str, &[u8], &[u8])> {
     if header.len() < 4 {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::InvalidHeader));
     }
     let n = LE::read_u32(&header[..4]) as usize;
     header = &header[4..];
 
     if header.len() < n {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::InvalidHeader));
     }
     let rec = &header[..n];
     header = &header[n..];
@@ -24,11 +25,11 @@ pub(crate) fn read_record(mut header: &[u8]) -> Result<(&str, &[u8], &[u8])> {
                 break;
             }
             0x20..=0x7e => (),
-            _ => return Err(Error::InvalidHeader),
+            _ => return Err(Error::new(ErrorKind::InvalidHeader)),
         }
     }
     if delim == 0 {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::InvalidHeader));
     }
     // SAFETY: the string is already checked
     let name = unsafe { str::from_utf8_unchecked(&rec[..delim]) };
@@ -44,13 +45,13 @@ pub(crate) fn check_op(val: &[u8], op: u8) -> Result<()> {
     if val.len() == 1 && val[0] == op {
         Ok(())
     } else {
-        Err(Error::InvalidRecord)
+        Err(Error::new(ErrorKind::InvalidRecord))
     }
 }
 
 pub(crate) fn set_field_u64(field: &mut Option<u64>, val: &[u8]) -> Result<()> {
     if val.len() != 8 || field.is_some() {
-        Err(Error::InvalidHeader)
+        Err(Error::new(ErrorKind::InvalidHeader))
     } else {
         *field = Some(LE::read_u64(val));
         Ok(())
@@ -59,7 +60,7 @@ pub(crate) fn set_field_u64(field: &mut Option<u64>, val: &[u8]) -> Result<()> {
 
 pub(crate) fn set_field_u32(field: &mut Option<u32>, val: &[u8]) -> Result<()> {
     if val.len() != 4 || field.is_some() {
-        Err(Error::InvalidHeader)
+        Err(Error::new(ErrorKind::InvalidHeader))
     } else {
         *field = Some(LE::read_u32(val));
         Ok(())
@@ -68,18 +69,40 @@ pub(crate) fn set_field_u32(field: &mut Option<u32>, val: &[u8]) -> Result<()> {
 
 pub(crate) fn set_field_str<'a>(field: &mut Option<&'a str>, val: &'a [u8]) -> Result<()> {
     if field.is_some() {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::InvalidHeader));
     }
-    *field = Some(str::from_utf8(val).map_err(|_| Error::InvalidHeader)?);
+    *field = Some(str::from_utf8(val).map_err(|_| Error::new(ErrorKind::InvalidHeader))?);
     Ok(())
 }
 
-pub(crate) fn set_field_time(field: &mut Option<u64>, val: &[u8]) -> Result<()> {
+pub(crate) fn set_field_time(field: &mut Option<Time>, val: &[u8]) -> Result<()> {
     if val.len() != 8 || field.is_some() {
-        return Err(Error::InvalidHeader);
+        return Err(Error::new(ErrorKind::InvalidHeader));
     }
-    let s = LE::read_u32(&val[..4]) as u64;
-    let ns = LE::read_u32(&val[4..]) as u64;
-    *field = Some(1_000_000_000 * s + ns);
+    let sec = LE::read_u32(&val[..4]);
+    let nsec = LE::read_u32(&val[4..]);
+    *field = Some(Time::new(sec, nsec));
     Ok(())
 }
+
+pub(crate) fn push_field(buf: &mut Vec<u8>, name: &str, val: &[u8]) {
+    let n = name.len() + 1 + val.len();
+    buf.extend_from_slice(&(n as u32).to_le_bytes());
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(b'=');
+    buf.extend_from_slice(val);
+}
+
+pub(crate) fn push_record(buf: &mut Vec<u8>, header: &[u8], data: &[u8]) {
+    buf.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    buf.extend_from_slice(header);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+pub(crate) fn time_to_bytes(t: Time) -> [u8; 8] {
+    let mut res = [0u8; 8];
+    LE::write_u32(&mut res[..4], t.sec());
+    LE::write_u32(&mut res[4..], t.nsec());
+    res
+}
@@ -0,0 +1,234 @@
+//! Recovery of readable records from corrupted chunk sections
+use crate::cursor::Cursor;
+use crate::field_iter::FieldIterator;
+use crate::record::read_op;
+use crate::record_types::{Chunk, IndexData, RawChunk, RecordGen};
+use crate::{ChunkRecord, Error, ErrorKind, Result};
+
+/// Maximum length of a record header considered plausible while
+/// resynchronizing.
+const MAX_HEADER_LEN: u32 = 4096;
+
+/// Item yielded by [`RecoveryIterator`].
+#[derive(Debug)]
+pub enum RecoveredRecord<'a> {
+    /// Record which was read without errors.
+    Record(ChunkRecord<'a>),
+    /// Chunk which data is readable only up to the point of corruption.
+    PartialChunk {
+        /// Chunk with salvaged data truncated to the last complete record
+        chunk: Chunk<'a>,
+        /// Position of the chunk record in the file
+        pos: u64,
+        /// Decompression or parsing error which occurred in the chunk
+        error: Error,
+    },
+    /// Range of bytes skipped while searching for the next valid record.
+    Skipped {
+        /// Position of the first skipped byte
+        start: u64,
+        /// Position of the first byte after the skipped range
+        end: u64,
+        /// Error which occurred while reading record at `start`
+        error: Error,
+    },
+}
+
+/// Iterator over records stored in the chunk section which recovers from
+/// corrupted data.
+///
+/// After encountering an invalid record, the iterator scans forward for
+/// the next plausible record header (`Chunk` or `IndexData` record with
+/// valid header fields and sane lengths) and reports the skipped byte range.
+/// Data of corrupted or truncated chunks is salvaged up to the point of
+/// corruption. Note that bzip2 data can be salvaged only up to the last
+/// complete bzip2 block (up to 900 kB of uncompressed data), while LZ4 data
+/// is salvaged up to the first invalid sequence.
+///
+/// ```
+/// use rosbag::{RecoveredRecord, RosBag};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = "dummy.bag";
+/// let bag = RosBag::new(path)?;
+/// for record in bag.recover_records() {
+///     match record {
+///         RecoveredRecord::Record(record) => {
+///             // ..
+///             # drop(record);
+///         }
+///         RecoveredRecord::PartialChunk { chunk, pos, error } => {
+///             println!("chunk at {} is corrupted: {}", pos, error);
+///             # drop(chunk);
+///         }
+///         RecoveredRecord::Skipped { start, end, error } => {
+///             println!("skipped bytes {}..{}: {}", start, end, error);
+///         }
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+///
+/// Created by the [`RosBag::recover_records`][crate::RosBag::recover_records]
+/// method.
+pub struct RecoveryIterator<'a> {
+    /// Cursor over the bag data which positions are relative to the file
+    /// beginning
+    pub(crate) cursor: Cursor<'a>,
+}
+
+impl<'a> RecoveryIterator<'a> {
+    fn read_record(&mut self, pos: u64) -> Result<RecoveredRecord<'a>> {
+        let c = &mut self.cursor;
+        let header = c.next_chunk()?;
+        match read_op(header)? {
+            Some(Chunk::OP) => {
+                let (raw, truncated) = RawChunk::read_truncated(header, c)?;
+                let (chunk, error) = raw.decompress_partial();
+                let error = if truncated {
+                    Some(Error::new(ErrorKind::OutOfBounds))
+                } else {
+                    error
+                };
+                Ok(match error {
+                    None => RecoveredRecord::Record(ChunkRecord::Chunk(chunk)),
+                    Some(error) => RecoveredRecord::PartialChunk { chunk, pos, error },
+                })
+            }
+            Some(IndexData::OP) => {
+                let index = IndexData::read(header, c)?;
+                Ok(RecoveredRecord::Record(ChunkRecord::IndexData(index)))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidRecord)),
+        }
+    }
+
+    /// Move cursor to the next plausible record starting at or after `pos`
+    /// and return its position.
+    fn resync(&mut self, pos: u64) -> u64 {
+        let end = self.cursor.len();
+        let pos = (pos..end)
+            .find(|&p| is_plausible(&self.cursor, p))
+            .unwrap_or(end);
+        self.cursor.seek(pos).expect("position is in bounds");
+        pos
+    }
+}
+
+/// Check if `pos` looks like a beginning of `Chunk` or `IndexData` record.
+fn is_plausible(c: &Cursor<'_>, pos: u64) -> bool {
+    let mut c = c.clone();
+    if c.seek(pos).is_err() {
+        return false;
+    }
+    let header_len = match c.next_u32() {
+        Ok(n) if (4..=MAX_HEADER_LEN).contains(&n) => n,
+        _ => return false,
+    };
+    let header = match c.next_bytes(header_len as u64) {
+        Ok(header) => header,
+        Err(_) => return false,
+    };
+    if FieldIterator::new(header).any(|f| f.is_err()) {
+        return false;
+    }
+    let data_len = match c.next_u32() {
+        Ok(n) => n as u64,
+        Err(_) => return false,
+    };
+    match read_op(header) {
+        // chunks can be truncated, their data is salvaged on a best-effort basis
+        Ok(Some(Chunk::OP)) => true,
+        Ok(Some(IndexData::OP)) => data_len <= c.left(),
+        _ => false,
+    }
+}
+
+impl<'a> Iterator for RecoveryIterator<'a> {
+    type Item = RecoveredRecord<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor.left() == 0 {
+            return None;
+        }
+        let pos = self.cursor.pos();
+        Some(match self.read_record(pos) {
+            Ok(record) => record,
+            Err(error) => {
+                let end = self.resync(pos + 1);
+                RecoveredRecord::Skipped {
+                    start: pos,
+                    end,
+                    error,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{ChunkRecord, ErrorKind, RecoveredRecord, RosBag};
+
+    fn kinds(bag: &RosBag<Vec<u8>>) -> Vec<&'static str> {
+        let records = bag.recover_records().map(|record| match record {
+            RecoveredRecord::Record(ChunkRecord::Chunk(_)) => "Chunk",
+            RecoveredRecord::Record(ChunkRecord::IndexData(_)) => "IndexData",
+            RecoveredRecord::PartialChunk { .. } => "PartialChunk",
+            RecoveredRecord::Skipped { .. } => "Skipped",
+        });
+        records.collect()
+    }
+
+    #[test]
+    fn corrupted_chunk() {
+        let mut data = write_bag(Compression::Bzip2, 4, 4);
+        let pos = data.windows(3).position(|w| w == b"BZh").unwrap();
+        // overwrite magic of the first compressed block
+        data[pos + 4..pos + 10].fill(0);
+        let bag = RosBag::from_bytes(data).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).min().unwrap();
+        let expected = [
+            "PartialChunk",
+            "IndexData",
+            "IndexData",
+            "Chunk",
+            "IndexData",
+            "IndexData",
+        ];
+        assert_eq!(kinds(&bag), expected);
+        match bag.recover_records().next() {
+            Some(RecoveredRecord::PartialChunk { pos, error, .. }) => {
+                assert_eq!(pos, chunk_pos);
+                assert_eq!(error.kind(), &ErrorKind::Decompression(Compression::Bzip2));
+            }
+            record => panic!("unexpected record: {:?}", record),
+        }
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let data = write_bag(Compression::Lz4, 40, 40);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).max().unwrap();
+        let bag = RosBag::reindex_bytes(data[..chunk_pos as usize + 600].to_vec()).unwrap();
+        assert_eq!(
+            kinds(&bag),
+            ["Chunk", "IndexData", "IndexData", "PartialChunk"]
+        );
+        match bag.recover_records().last() {
+            Some(RecoveredRecord::PartialChunk { chunk, pos, error }) => {
+                assert_eq!(pos, chunk_pos);
+                assert_eq!(error.kind(), &ErrorKind::OutOfBounds);
+                // salvaged data is truncated to the last complete record
+                let records: Vec<_> = chunk.messages().map(|r| r.unwrap()).collect();
+                assert!(!records.is_empty() && records.len() < 40);
+            }
+            record => panic!("unexpected record: {:?}", record),
+        }
+        // messages of the truncated chunk are not indexed
+        assert_eq!(bag.messages().count(), 40);
+    }
+}
@@ -0,0 +1,223 @@
+//! Reconstruction of the index section by scanning the chunk section
+use crate::cursor::Cursor;
+use crate::msg_iter::{MessageRecord, MessageRecordsIterator};
+use crate::record::read_op;
+use crate::record_types::{Chunk, IndexData, RawChunk, RecordGen};
+use crate::time::Time;
+use crate::writer::{encode_chunk_info, encode_connection, encode_index_data};
+use crate::Result;
+use std::collections::BTreeMap;
+
+/// Index information rebuilt from the chunk section.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RebuiltIndex {
+    /// Encoded `Connection` and `ChunkInfo` records which replace the index
+    /// section of the bag
+    pub(crate) section: Vec<u8>,
+    /// Encoded `IndexData` records by chunk position
+    pub(crate) chunk_index: BTreeMap<u64, Vec<u8>>,
+    /// Position of the first byte after the last complete record in the
+    /// chunk section
+    pub(crate) end_pos: u64,
+    pub(crate) conn_count: u32,
+    pub(crate) chunk_count: u32,
+}
+
+impl RebuiltIndex {
+    /// Scan records in `data` starting at `start_pos` until the first
+    /// truncated or invalid record.
+    ///
+    /// `IndexData` records present in the chunk section are ignored and
+    /// rebuilt from messages stored in the chunks.
+    pub(crate) fn scan(data: &[u8], start_pos: u64) -> Result<Self> {
+        let mut c = Cursor::new(data);
+        c.seek(start_pos)?;
+
+        let mut connections = BTreeMap::new();
+        let mut chunk_infos = Vec::new();
+        let mut res = Self {
+            end_pos: start_pos,
+            ..Default::default()
+        };
+        while c.left() != 0 {
+            let pos = c.pos();
+            let header = match c.next_chunk() {
+                Ok(header) => header,
+                Err(_) => {
+                    log::warn!("truncated record at {}, stopping reindexing", pos);
+                    break;
+                }
+            };
+            match read_op(header) {
+                Ok(Some(Chunk::OP)) => {
+                    let chunk = match RawChunk::read_with_header(header, &mut c)
+                        .and_then(|chunk| chunk.decompress())
+                    {
+                        Ok(chunk) => chunk,
+                        Err(err) => {
+                            log::warn!("invalid chunk at {}: {}, stopping reindexing", pos, err);
+                            break;
+                        }
+                    };
+                    let mut index = match index_chunk(&chunk.data, &mut connections) {
+                        Ok(index) => index,
+                        Err(err) => {
+                            log::warn!("invalid chunk at {}: {}, stopping reindexing", pos, err);
+                            break;
+                        }
+                    };
+                    index.retain(|conn_id, _| {
+                        let known = connections.contains_key(conn_id);
+                        if !known {
+                            log::warn!(
+                                "chunk at {} has messages of unknown connection {}",
+                                pos,
+                                conn_id
+                            );
+                        }
+                        known
+                    });
+                    if !index.is_empty() {
+                        let mut buf = Vec::new();
+                        let (mut start_time, mut end_time) = (Time::MAX, Time::ZERO);
+                        let mut counts = BTreeMap::new();
+                        for (&conn_id, entries) in index.iter() {
+                            encode_index_data(&mut buf, conn_id, entries);
+                            counts.insert(conn_id, entries.len() as u32);
+                            for &(time, _) in entries {
+                                start_time = start_time.min(time);
+                                end_time = end_time.max(time);
+                            }
+                        }
+                        res.chunk_index.insert(pos, buf);
+                        encode_chunk_info(&mut chunk_infos, pos, start_time, end_time, &counts);
+                        res.chunk_count += 1;
+                    }
+                }
+                Ok(Some(IndexData::OP)) => {
+                    if c.next_chunk().is_err() {
+                        log::warn!("truncated record at {}, stopping reindexing", pos);
+                        break;
+                    }
+                }
+                _ => {
+                    log::warn!("unexpected record at {}, stopping reindexing", pos);
+                    break;
+                }
+            }
+            res.end_pos = c.pos();
+        }
+
+        res.conn_count = connections.len() as u32;
+        for record in connections.values() {
+            res.section.extend_from_slice(record);
+        }
+        res.section.extend_from_slice(&chunk_infos);
+        Ok(res)
+    }
+}
+
+/// Collect `(time, offset)` entries of messages stored in the chunk data
+/// by connection ID and register encountered connections.
+fn index_chunk(
+    data: &[u8],
+    connections: &mut BTreeMap<u32, Vec<u8>>,
+) -> Result<BTreeMap<u32, Vec<(Time, u32)>>> {
+    let mut index: BTreeMap<u32, Vec<(Time, u32)>> = BTreeMap::new();
+    let mut records = MessageRecordsIterator::new(data);
+    loop {
+        let offset = records.cursor.pos() as u32;
+        match records.next() {
+            Some(Ok(MessageRecord::Connection(conn))) => {
+                connections
+                    .entry(conn.id)
+                    .or_insert_with(|| encode_connection(&conn));
+            }
+            Some(Ok(MessageRecord::MessageData(msg))) => {
+                let entries = index.entry(msg.conn_id).or_default();
+                entries.push((msg.time, offset));
+            }
+            Some(Err(err)) => return Err(err),
+            None => break,
+        }
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::RosBag;
+    use std::io::Cursor;
+
+    fn messages<D: AsRef<[u8]>>(bag: &RosBag<D>) -> Vec<(u32, String, Vec<u8>)> {
+        let messages = bag.messages().map(|msg| {
+            let msg = msg.unwrap();
+            (msg.conn.id, msg.time.to_string(), msg.data().to_vec())
+        });
+        messages.collect()
+    }
+
+    #[test]
+    fn stripped_index() {
+        let data = write_bag(Compression::Lz4, 10, 4);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let expected = messages(&bag);
+
+        let stripped = data[..bag.index_pos].to_vec();
+        // `index_pos` points at the end of file, so the index is empty
+        let bag = RosBag::from_bytes(stripped.clone()).unwrap();
+        assert_eq!(bag.connections().count(), 0);
+        assert_eq!(bag.messages().count(), 0);
+        let bag = RosBag::reindex_bytes(stripped).unwrap();
+        assert_eq!((bag.get_conn_count(), bag.get_chunk_count()), (2, 5));
+        assert_eq!(bag.connections().count(), 2);
+        assert_eq!(bag.chunk_infos().count(), 5);
+        assert_eq!(messages(&bag), expected);
+    }
+
+    #[test]
+    fn truncated_index() {
+        let data = write_bag(Compression::Bzip2, 10, 4);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let expected = messages(&bag);
+
+        let truncated = data[..bag.index_pos + 10].to_vec();
+        assert!(RosBag::from_bytes(truncated.clone())
+            .unwrap()
+            .index_error()
+            .is_some());
+        let bag = RosBag::reindex_bytes(truncated).unwrap();
+        assert_eq!((bag.get_conn_count(), bag.get_chunk_count()), (2, 5));
+        assert_eq!(messages(&bag), expected);
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let data = write_bag(Compression::None, 10, 4);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).max().unwrap();
+        let expected: Vec<_> = messages(&bag).into_iter().take(16).collect();
+
+        let bag = RosBag::reindex_bytes(data[..chunk_pos as usize + 100].to_vec()).unwrap();
+        assert_eq!((bag.get_conn_count(), bag.get_chunk_count()), (2, 4));
+        assert_eq!(bag.index_pos as u64, chunk_pos);
+        assert_eq!(messages(&bag), expected);
+    }
+
+    #[test]
+    fn write_reindexed() {
+        let data = write_bag(Compression::Bzip2, 10, 4);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let expected = messages(&bag);
+
+        let bag = RosBag::reindex_bytes(data[..bag.index_pos].to_vec()).unwrap();
+        let out = bag.write_reindexed(Cursor::new(Vec::new())).unwrap();
+        let bag = RosBag::from_bytes(out.into_inner()).unwrap();
+        assert!(bag.verify().is_ok(), "{}", bag.verify());
+        assert_eq!((bag.get_conn_count(), bag.get_chunk_count()), (2, 5));
+        assert_eq!(bag.message_count(0) + bag.message_count(1), 20);
+        assert_eq!(messages(&bag), expected);
+    }
+}
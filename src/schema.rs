@@ -0,0 +1,534 @@
+//! Parser for ROS message definitions.
+//!
+//! [`Connection::message_definition`][crate::record_types::Connection]
+//! contains text of the `.msg` file for the connection message type
+//! followed by definitions of all its dependencies (as produced by
+//! `gendeps --cat`). Each dependency section is separated from the previous
+//! one by a line of `=` characters and starts with the `MSG: pkg/Type` line.
+//!
+//! # Example
+//! ```
+//! use rosbag::schema::{FieldType, MessageSchema, PrimitiveType};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let definition = "\
+//! Header header
+//! float64[9] covariance
+//! ================================================================================
+//! MSG: std_msgs/Header
+//! uint32 seq
+//! time stamp
+//! string frame_id
+//! ";
+//! let schema = MessageSchema::parse("foo_msgs/Covariance", definition)?;
+//! let root = &schema.root;
+//! assert_eq!(root.fields[0].tp, FieldType::Message("std_msgs/Header".into()));
+//! assert_eq!(root.fields[1].tp.to_string(), "float64[9]");
+//! let header = schema.get("std_msgs/Header").unwrap();
+//! assert_eq!(header.fields[1].tp, FieldType::Primitive(PrimitiveType::Time));
+//! # Ok(()) }
+//! ```
+use crate::{Error, ErrorKind, Result};
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Primitive (built-in) ROS types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveType {
+    /// `bool`
+    Bool,
+    /// `int8`
+    Int8,
+    /// `uint8`
+    UInt8,
+    /// `int16`
+    Int16,
+    /// `uint16`
+    UInt16,
+    /// `int32`
+    Int32,
+    /// `uint32`
+    UInt32,
+    /// `int64`
+    Int64,
+    /// `uint64`
+    UInt64,
+    /// `float32`
+    Float32,
+    /// `float64`
+    Float64,
+    /// `string`
+    String,
+    /// `time`
+    Time,
+    /// `duration`
+    Duration,
+    /// `byte` (deprecated alias for `int8`)
+    Byte,
+    /// `char` (deprecated alias for `uint8`)
+    Char,
+}
+
+impl PrimitiveType {
+    /// Get primitive type by its name in message definitions.
+    pub fn from_name(name: &str) -> Option<Self> {
+        use PrimitiveType::*;
+        Some(match name {
+            "bool" => Bool,
+            "int8" => Int8,
+            "uint8" => UInt8,
+            "int16" => Int16,
+            "uint16" => UInt16,
+            "int32" => Int32,
+            "uint32" => UInt32,
+            "int64" => Int64,
+            "uint64" => UInt64,
+            "float32" => Float32,
+            "float64" => Float64,
+            "string" => String,
+            "time" => Time,
+            "duration" => Duration,
+            "byte" => Byte,
+            "char" => Char,
+            _ => return None,
+        })
+    }
+
+    /// Get name of the type as used in message definitions.
+    pub fn name(self) -> &'static str {
+        use PrimitiveType::*;
+        match self {
+            Bool => "bool",
+            Int8 => "int8",
+            UInt8 => "uint8",
+            Int16 => "int16",
+            UInt16 => "uint16",
+            Int32 => "int32",
+            UInt32 => "uint32",
+            Int64 => "int64",
+            UInt64 => "uint64",
+            Float32 => "float32",
+            Float64 => "float64",
+            String => "string",
+            Time => "time",
+            Duration => "duration",
+            Byte => "byte",
+            Char => "char",
+        }
+    }
+}
+
+impl fmt::Display for PrimitiveType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Type of a message field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    /// Primitive type.
+    Primitive(PrimitiveType),
+    /// Nested message type with a fully qualified name (e.g. `std_msgs/Header`).
+    Message(String),
+    /// Array of elements.
+    Array {
+        /// Type of array elements
+        tp: Box<FieldType>,
+        /// Array length for fixed-size arrays, `None` for variable-size ones
+        len: Option<usize>,
+    },
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Primitive(tp) => write!(f, "{}", tp),
+            FieldType::Message(name) => f.write_str(name),
+            FieldType::Array { tp, len: Some(n) } => write!(f, "{}[{}]", tp, n),
+            FieldType::Array { tp, len: None } => write!(f, "{}[]", tp),
+        }
+    }
+}
+
+/// Message field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Field name
+    pub name: String,
+    /// Field type
+    pub tp: FieldType,
+}
+
+/// Message constant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    /// Constant type
+    pub tp: PrimitiveType,
+    /// Constant name
+    pub name: String,
+    /// Constant value as written in the definition (without surrounding
+    /// whitespace)
+    pub value: String,
+}
+
+/// Definition of a single message type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDefinition {
+    /// Fully qualified message type name (e.g. `std_msgs/Header`)
+    pub name: String,
+    /// Message fields in the serialization order
+    pub fields: Vec<Field>,
+    /// Message constants
+    pub constants: Vec<Constant>,
+}
+
+/// Parsed message definition together with definitions of all types it
+/// depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSchema {
+    /// Definition of the top-level message type
+    pub root: MessageDefinition,
+    /// Definitions of nested message types
+    pub dependencies: Vec<MessageDefinition>,
+}
+
+impl MessageSchema {
+    /// Parse message definition text for message type `tp`.
+    ///
+    /// All nested message types are resolved to their fully qualified names
+    /// and checked to be present in the definition. Types which contain
+    /// themselves directly or through other types are rejected.
+    pub fn parse(tp: &str, text: &str) -> Result<Self> {
+        let mut sections = split_sections(text);
+        let root = parse_definition(tp, sections.remove(0))?;
+        let dependencies = sections
+            .into_iter()
+            .map(|section| {
+                let mut lines = section.splitn(2, '\n');
+                let first = lines.next().unwrap_or("").trim();
+                let name = first
+                    .strip_prefix("MSG:")
+                    .map(str::trim)
+                    .ok_or_else(|| invalid(format!("expected `MSG:` line, got {:?}", first)))?;
+                parse_definition(name, lines.next().unwrap_or(""))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let schema = Self { root, dependencies };
+        for def in schema.definitions() {
+            for field in def.fields.iter() {
+                if let Some(name) = field.tp.message_name() {
+                    if schema.get(name).is_none() {
+                        return Err(invalid(format!(
+                            "definition of {} used by {} is missing",
+                            name, def.name,
+                        )));
+                    }
+                }
+            }
+        }
+        let mut checked = Vec::new();
+        for def in schema.definitions() {
+            schema.check_recursion(&def.name, &mut checked, &mut Vec::new())?;
+        }
+        Ok(schema)
+    }
+
+    /// Check that message type `name` and its dependencies do not contain
+    /// themselves. `checked` holds types for which the check has passed.
+    fn check_recursion<'a>(
+        &'a self,
+        name: &'a str,
+        checked: &mut Vec<&'a str>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        if checked.contains(&name) {
+            return Ok(());
+        }
+        if stack.contains(&name) {
+            return Err(invalid(format!("recursive definition of {}", name)));
+        }
+        let def = self.get(name).expect("presence is checked");
+        stack.push(name);
+        for field in def.fields.iter() {
+            if let Some(name) = field.tp.message_name() {
+                self.check_recursion(name, checked, stack)?;
+            }
+        }
+        stack.pop();
+        checked.push(name);
+        Ok(())
+    }
+
+    /// Get definition of message type with the given fully qualified name.
+    pub fn get(&self, name: &str) -> Option<&MessageDefinition> {
+        self.definitions().find(|def| def.name == name)
+    }
+
+    /// Iterate over the root definition followed by its dependencies.
+    pub fn definitions(&self) -> impl Iterator<Item = &MessageDefinition> {
+        std::iter::once(&self.root).chain(self.dependencies.iter())
+    }
+
+    /// Compute MD5 sum of the root message type.
+    ///
+    /// The sum gets computed in the same way as done by ROS: over normalized
+    /// text of the definition with constants listed first, comments and
+    /// whitespace removed and nested message types replaced by their own MD5
+    /// sums.
+    pub fn md5sum(&self) -> Result<[u8; 16]> {
+        self.md5sum_of(&self.root.name, &mut HashMap::new(), &mut Vec::new())
+    }
+
+    fn md5sum_of<'a>(
+        &'a self,
+        name: &'a str,
+        cache: &mut HashMap<&'a str, [u8; 16]>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<[u8; 16]> {
+        if let Some(sum) = cache.get(name) {
+            return Ok(*sum);
+        }
+        if stack.contains(&name) {
+            return Err(invalid(format!("recursive definition of {}", name)));
+        }
+        let def = self
+            .get(name)
+            .ok_or_else(|| invalid(format!("definition of {} is missing", name)))?;
+
+        stack.push(name);
+        let mut lines = Vec::with_capacity(def.constants.len() + def.fields.len());
+        for c in def.constants.iter() {
+            lines.push(format!("{} {}={}", c.tp, c.name, c.value));
+        }
+        for field in def.fields.iter() {
+            lines.push(match field.tp.message_name() {
+                Some(name) => {
+                    let sum = self.md5sum_of(name, cache, stack)?;
+                    let mut buf = [0u8; 32];
+                    let sum = base16ct::lower::encode_str(&sum, &mut buf)
+                        .expect("buffer has correct length");
+                    format!("{} {}", sum, field.name)
+                }
+                None => format!("{} {}", field.tp, field.name),
+            });
+        }
+        stack.pop();
+
+        let sum: [u8; 16] = Md5::digest(lines.join("\n").as_bytes()).into();
+        cache.insert(name, sum);
+        Ok(sum)
+    }
+}
+
+impl FieldType {
+    /// Get name of the nested message type, including array elements.
+    pub fn message_name(&self) -> Option<&str> {
+        match self {
+            FieldType::Primitive(_) => None,
+            FieldType::Message(name) => Some(name),
+            FieldType::Array { tp, .. } => tp.message_name(),
+        }
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidMessageDefinition(msg))
+}
+
+/// Split text on separator lines consisting only of `=` characters.
+fn split_sections(text: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.len() >= 3 && trimmed.bytes().all(|b| b == b'=') {
+            sections.push(&text[start..pos]);
+            start = pos + line.len();
+        }
+        pos += line.len();
+    }
+    sections.push(&text[start..]);
+    sections
+}
+
+fn parse_definition(name: &str, text: &str) -> Result<MessageDefinition> {
+    let package = match name.split_once('/') {
+        Some((package, tp)) if !package.is_empty() && !tp.is_empty() => package,
+        _ => return Err(invalid(format!("invalid message type name: {:?}", name))),
+    };
+    let mut fields = Vec::new();
+    let mut constants = Vec::new();
+
+    for line in text.lines() {
+        let clean = line.split('#').next().unwrap_or("").trim();
+        if clean.is_empty() {
+            continue;
+        }
+        if clean.contains('=') {
+            constants.push(parse_constant(line, clean)?);
+        } else {
+            fields.push(parse_field(package, clean)?);
+        }
+    }
+
+    Ok(MessageDefinition {
+        name: name.to_string(),
+        fields,
+        constants,
+    })
+}
+
+fn parse_constant(line: &str, clean: &str) -> Result<Constant> {
+    let err = || invalid(format!("invalid constant declaration: {:?}", line));
+    let (tp, rest) = clean.split_once(char::is_whitespace).ok_or_else(err)?;
+    let tp = match PrimitiveType::from_name(tp) {
+        Some(PrimitiveType::Time) | Some(PrimitiveType::Duration) | None => return Err(err()),
+        Some(tp) => tp,
+    };
+    let (name, value) = if tp == PrimitiveType::String {
+        // string constants contain everything to the right of `=`
+        let line = line.trim_start();
+        let rest = &line[line.find(char::is_whitespace).ok_or_else(err)?..];
+        let (name, value) = rest.split_once('=').ok_or_else(err)?;
+        (name.trim(), value.trim())
+    } else {
+        let (name, value) = rest.split_once('=').ok_or_else(err)?;
+        (name.trim(), value.trim())
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(err());
+    }
+    Ok(Constant {
+        tp,
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+fn parse_field(package: &str, clean: &str) -> Result<Field> {
+    let err = || invalid(format!("invalid field declaration: {:?}", clean));
+    let mut tokens = clean.split_whitespace();
+    let (tp, name) = match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(tp), Some(name), None) => (tp, name),
+        _ => return Err(err()),
+    };
+
+    let tp = match tp.strip_suffix(']') {
+        Some(tp) => {
+            let (base, len) = tp.split_once('[').ok_or_else(err)?;
+            let len = match len {
+                "" => None,
+                len => Some(len.parse().map_err(|_| err())?),
+            };
+            let tp = Box::new(parse_base_type(package, base).ok_or_else(err)?);
+            FieldType::Array { tp, len }
+        }
+        None => parse_base_type(package, tp).ok_or_else(err)?,
+    };
+
+    Ok(Field {
+        name: name.to_string(),
+        tp,
+    })
+}
+
+fn parse_base_type(package: &str, tp: &str) -> Option<FieldType> {
+    if let Some(tp) = PrimitiveType::from_name(tp) {
+        return Some(FieldType::Primitive(tp));
+    }
+    if tp.is_empty() || tp.contains(|c: char| c == '[' || c == ']') {
+        return None;
+    }
+    Some(FieldType::Message(match tp {
+        "Header" => "std_msgs/Header".to_string(),
+        tp if tp.contains('/') => tp.to_string(),
+        tp => format!("{}/{}", package, tp),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageSchema;
+    use crate::test_utils::connection;
+    use crate::ErrorKind;
+
+    #[test]
+    fn md5sums() {
+        let cases = [
+            ("std_msgs/Empty", "", "d41d8cd98f00b204e9800998ecf8427e"),
+            (
+                "std_msgs/String",
+                "string data\n",
+                "992ce8a1687cec8c8bd883ec73ca41d1",
+            ),
+            (
+                "std_msgs/Header",
+                "# sequence ID\nuint32 seq\ntime stamp\n\n# frame\nstring frame_id\n",
+                "2176decaecbce78abc3b96ef049fabed",
+            ),
+            (
+                "sensor_msgs/ChannelFloat32",
+                "string name\nfloat32[] values\n",
+                "3d40139cdd33dfedcb71ffeeeb42ae7f",
+            ),
+            (
+                "geometry_msgs/Twist",
+                "Vector3  linear\nVector3  angular\n\n\
+                 ================================================================================\n\
+                 MSG: geometry_msgs/Vector3\nfloat64 x\nfloat64 y\nfloat64 z\n",
+                "9f195f881246fdfa2798d1d3eebca84a",
+            ),
+            (
+                "geometry_msgs/PoseStamped",
+                "Header header\nPose pose\n\
+                 ===\nMSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n\
+                 ===\nMSG: geometry_msgs/Pose\nPoint position\nQuaternion orientation\n\
+                 ===\nMSG: geometry_msgs/Point\nfloat64 x\nfloat64 y\nfloat64 z\n\
+                 ===\nMSG: geometry_msgs/Quaternion\nfloat64 x\nfloat64 y\nfloat64 z\n\
+                 float64 w\n",
+                "d3812c3cbc69362b77dc0b19b345f8f5",
+            ),
+            (
+                "actionlib_msgs/GoalStatus",
+                "GoalID goal_id\nuint8 status\n\
+                 uint8 PENDING         = 0   # not processed yet\n\
+                 uint8 ACTIVE          = 1\nuint8 PREEMPTED       = 2\n\
+                 uint8 SUCCEEDED       = 3\nuint8 ABORTED         = 4\n\
+                 uint8 REJECTED        = 5\nuint8 PREEMPTING      = 6\n\
+                 uint8 RECALLING       = 7\nuint8 RECALLED        = 8\n\
+                 uint8 LOST            = 9\n\n\
+                 #Allow for the user to associate a string with GoalStatus for debugging\n\
+                 string text\n\n\
+                 ===\nMSG: actionlib_msgs/GoalID\ntime stamp\nstring id\n",
+                "d388f9b87b3c471f784434d671988d4a",
+            ),
+        ];
+        for (tp, text, expected) in cases {
+            let md5sum = MessageSchema::parse(tp, text).unwrap().md5sum().unwrap();
+            let hex: String = md5sum.iter().map(|b| format!("{:02x}", b)).collect();
+            assert_eq!(hex, expected, "{}", tp);
+        }
+        let conn = connection(0);
+        assert_eq!(conn.compute_md5sum().unwrap(), conn.md5sum);
+    }
+
+    #[test]
+    fn recursive_definitions() {
+        let cases = [
+            ("A next\n", "a/A"),
+            ("B b\n===\nMSG: a/B\nA[] a\n", "a/A"),
+            // unused dependency
+            ("string data\n===\nMSG: a/B\nB b\n", "a/B"),
+        ];
+        for (text, name) in cases {
+            let err = MessageSchema::parse("a/A", text).unwrap_err();
+            let msg = format!("recursive definition of {}", name);
+            assert_eq!(err.kind(), &ErrorKind::InvalidMessageDefinition(msg));
+        }
+        MessageSchema::parse("a/A", "B b1\nB b2\n===\nMSG: a/B\nint8 x\n").unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Reference-counted bag handle with non-borrowing iterators
+use crate::messages::SharedMessages;
+use crate::{ChunkRecord, Result, RosBag};
+use memmap2::Mmap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Shared handle to an open bag.
+///
+/// `SharedBag` wraps [`RosBag`] into an `Arc`, cloning the handle is cheap
+/// and all methods of `RosBag` are available through `Deref`. Unlike
+/// iterators of `RosBag`, which borrow the bag, [`SharedBag::chunk_records`]
+/// and [`SharedBag::messages`] hold a clone of the handle and have no
+/// lifetime parameter, so they can be stored together with the bag, returned
+/// from functions and moved into other threads.
+///
+/// ```
+/// use rosbag::{OwnedMessage, Result, SharedBag, SharedMessages};
+///
+/// fn imu_messages(path: &str) -> Result<SharedMessages> {
+///     let bag = SharedBag::open(path)?;
+///     Ok(bag.messages().topics(["/imu"]))
+/// }
+///
+/// # fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+/// let worker = std::thread::spawn(move || -> Result<usize> {
+///     let mut count = 0;
+///     for msg in imu_messages("dummy.bag")? {
+///         let msg: OwnedMessage = msg?;
+///         // ..
+///         # drop(msg);
+///         count += 1;
+///     }
+///     Ok(count)
+/// });
+/// let count = worker.join().unwrap()?;
+/// # drop(count);
+/// # Ok(()) }
+/// ```
+pub struct SharedBag<D = Mmap>(Arc<RosBag<D>>);
+
+impl SharedBag {
+    /// Open and memory map rosbag file, see [`RosBag::new`].
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        RosBag::new(path).map(Self::new)
+    }
+}
+
+impl<D: AsRef<[u8]>> SharedBag<D> {
+    /// Create shared handle to `bag`.
+    pub fn new(bag: RosBag<D>) -> Self {
+        SharedBag(Arc::new(bag))
+    }
+
+    /// Get iterator over records in the chunk section which owns a handle
+    /// to the bag.
+    ///
+    /// Decompressed chunks are shared with the chunk cache, while data of
+    /// uncompressed chunks gets copied, see [`Chunk::into_owned`][crate::record_types::Chunk::into_owned].
+    pub fn chunk_records(&self) -> SharedChunkRecords<D> {
+        SharedChunkRecords {
+            pos: self.0.start_pos as u64,
+            bag: self.0.clone(),
+        }
+    }
+
+    /// Get iterator over messages stored in the bag sorted by their receive
+    /// time which owns a handle to the bag.
+    pub fn messages(&self) -> SharedMessages<D> {
+        SharedMessages::new(self.0.clone())
+    }
+}
+
+impl<D> Clone for SharedBag<D> {
+    fn clone(&self) -> Self {
+        SharedBag(self.0.clone())
+    }
+}
+
+impl<D> Deref for SharedBag<D> {
+    type Target = RosBag<D>;
+
+    fn deref(&self) -> &RosBag<D> {
+        &self.0
+    }
+}
+
+impl<D: AsRef<[u8]>> From<RosBag<D>> for SharedBag<D> {
+    fn from(bag: RosBag<D>) -> Self {
+        Self::new(bag)
+    }
+}
+
+/// Iterator over records stored in the chunk section of a shared bag.
+///
+/// Same as [`ChunkRecordsIterator`][crate::ChunkRecordsIterator], but holds
+/// a clone of the bag handle. Created by the [`SharedBag::chunk_records`]
+/// method.
+pub struct SharedChunkRecords<D = Mmap> {
+    bag: Arc<RosBag<D>>,
+    /// Position of the next record in the bag file
+    pos: u64,
+}
+
+impl<D: AsRef<[u8]>> SharedChunkRecords<D> {
+    /// Jump to the given position in the file, see
+    /// [`ChunkRecordsIterator::seek`][crate::ChunkRecordsIterator::seek].
+    pub fn seek(&mut self, pos: u64) -> Result<()> {
+        self.bag.chunk_records().seek(pos)?;
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+impl<D: AsRef<[u8]>> Iterator for SharedChunkRecords<D> {
+    type Item = Result<ChunkRecord<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut records = self.bag.chunk_records();
+        if let Err(err) = records.seek(self.pos) {
+            // stop after reporting the error
+            self.pos = records.offset + records.cursor.len();
+            return Some(Err(err));
+        }
+        let res = records.next()?;
+        self.pos = records.offset + records.cursor.pos();
+        Some(res.map(|record| match record {
+            ChunkRecord::Chunk(chunk) => ChunkRecord::Chunk(chunk.into_owned()),
+            ChunkRecord::IndexData(index) => ChunkRecord::IndexData(index.into_owned()),
+        }))
+    }
+}
+
+/// Check that bags and owned records can be shared between threads and
+/// iterators can be sent to other threads.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn sync<T: Send + Sync>() {}
+    fn send<T: Send>() {}
+    sync::<RosBag>();
+    sync::<RosBag<Vec<u8>>>();
+    sync::<SharedBag>();
+    sync::<crate::OwnedMessage>();
+    sync::<crate::Error>();
+    send::<SharedChunkRecords>();
+    send::<SharedMessages>();
+}
@@ -0,0 +1,285 @@
+//! Sequential reading of bags from non-seekable sources
+use crate::record::read_op;
+use crate::record_types::{Chunk, ChunkInfo, Connection, IndexData, RecordGen};
+use crate::{read_bag_header, Error, ErrorKind, Result, BAG_HEADER, VERSION_LEN, VERSION_STRING};
+use std::io::{self, Read};
+
+/// Streaming reader of ROS bags which processes a `Read` source strictly
+/// front-to-back.
+///
+/// The reader parses the bag header and then yields chunks as they arrive.
+/// Chunk data is decompressed while it is being read, so memory use is
+/// bounded by a single decompressed chunk. `IndexData` records in the chunk
+/// section are skipped and iteration stops at the beginning of the index
+/// section or at the end of the stream, so bags which recording was
+/// interrupted before the index section was written can be processed as
+/// well. After an error the iterator yields no further items.
+///
+/// ```
+/// use rosbag::{BagStream, MessageRecord};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// // any `Read` source can be used, e.g. `std::io::stdin()`
+/// let file = std::fs::File::open("dummy.bag")?;
+/// for chunk in BagStream::new(std::io::BufReader::new(file))? {
+///     for record in chunk?.messages() {
+///         match record? {
+///             MessageRecord::Connection(conn) => {
+///                 // ..
+///                 # drop(conn);
+///             }
+///             MessageRecord::MessageData(msg) => {
+///                 // ..
+///                 # drop(msg);
+///             }
+///         }
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+pub struct BagStream<R> {
+    reader: R,
+    /// Position of the next record relative to the stream beginning
+    pos: u64,
+    /// Position of the index section, zero if it was not written
+    index_pos: u64,
+    conn_count: u32,
+    chunk_count: u32,
+    done: bool,
+}
+
+impl<R: Read> BagStream<R> {
+    /// Read bag header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut version = [0u8; VERSION_LEN as usize];
+        reader.read_exact(&mut version)?;
+        if version != VERSION_STRING.as_bytes() {
+            return Err(Error::new(ErrorKind::UnsupportedVersion).at(0));
+        }
+        let mut stream = Self {
+            reader,
+            pos: VERSION_LEN,
+            index_pos: 0,
+            conn_count: 0,
+            chunk_count: 0,
+            done: false,
+        };
+        let at = |e: Error| e.in_record(BAG_HEADER).at(VERSION_LEN);
+        let header = match stream.read_header().map_err(at)? {
+            Some(header) => header,
+            None => return Err(at(unexpected_eof())),
+        };
+        let header = read_bag_header(&header).map_err(at)?;
+        stream.skip_data().map_err(at)?;
+        stream.index_pos = header.index_pos;
+        stream.conn_count = header.conn_count;
+        stream.chunk_count = header.chunk_count;
+        Ok(stream)
+    }
+
+    /// Get connection count stored in the bag header.
+    ///
+    /// Bags which recording was interrupted may store zero.
+    pub fn get_conn_count(&self) -> u32 {
+        self.conn_count
+    }
+
+    /// Get chunk count stored in the bag header.
+    ///
+    /// Bags which recording was interrupted may store zero.
+    pub fn get_chunk_count(&self) -> u32 {
+        self.chunk_count
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read `u32` value, returns `None` if the stream ends before it.
+    fn read_u32(&mut self) -> Result<Option<u32>> {
+        let mut buf = [0u8; 4];
+        let mut n = 0;
+        while n < buf.len() {
+            match self.reader.read(&mut buf[n..]) {
+                Ok(0) if n == 0 => return Ok(None),
+                Ok(0) => return Err(unexpected_eof()),
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.pos += 4;
+        Ok(Some(u32::from_le_bytes(buf)))
+    }
+
+    /// Read record header, returns `None` if the stream ends before it.
+    fn read_header(&mut self) -> Result<Option<Vec<u8>>> {
+        let len = match self.read_u32()? {
+            Some(len) => len as u64,
+            None => return Ok(None),
+        };
+        let mut header = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut header)?;
+        if header.len() as u64 != len {
+            return Err(unexpected_eof());
+        }
+        self.pos += len;
+        Ok(Some(header))
+    }
+
+    /// Get reader limited to data of the current record.
+    fn data(&mut self) -> Result<io::Take<&mut R>> {
+        let len = match self.read_u32()? {
+            Some(len) => len as u64,
+            None => return Err(unexpected_eof()),
+        };
+        self.pos += len;
+        Ok((&mut self.reader).take(len))
+    }
+
+    /// Skip data of the current record.
+    fn skip_data(&mut self) -> Result<()> {
+        let mut data = self.data()?;
+        drain(&mut data)
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Chunk<'static>>> {
+        loop {
+            let pos = self.pos;
+            if self.index_pos != 0 && pos >= self.index_pos {
+                return Ok(None);
+            }
+            let at = |e: Error| e.at(pos);
+            let header = match self.read_header().map_err(at)? {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+            let op = read_op(&header).map_err(at)?;
+            match op {
+                Some(Chunk::OP) => {
+                    let mut data = self.data().map_err(at)?;
+                    let chunk = Chunk::read_from(&header, &mut data);
+                    // report truncated stream before decompression errors
+                    drain(&mut data).map_err(at)?;
+                    return chunk.map(Some).map_err(at);
+                }
+                Some(IndexData::OP) => self.skip_data().map_err(at)?,
+                // index section of a bag with unknown `index_pos`
+                Some(Connection::OP) | Some(ChunkInfo::OP) => return Ok(None),
+                _ => {
+                    let err = Error::new(ErrorKind::InvalidRecord).in_field("op");
+                    return Err(at(err));
+                }
+            }
+        }
+    }
+}
+
+/// Consume the rest of the record data.
+fn drain<R: Read>(data: &mut io::Take<R>) -> Result<()> {
+    io::copy(data, &mut io::sink())?;
+    match data.limit() {
+        0 => Ok(()),
+        _ => Err(unexpected_eof()),
+    }
+}
+
+/// Error for the stream which ends in the middle of a record.
+fn unexpected_eof() -> Error {
+    io::Error::from(io::ErrorKind::UnexpectedEof).into()
+}
+
+impl<R: Read> Iterator for BagStream<R> {
+    type Item = Result<Chunk<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_chunk().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BagStream;
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{ErrorKind, MessageRecord, RosBag};
+    use std::io::{self, Read};
+
+    /// Non-seekable reader which returns at most 3 bytes per call.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3);
+            (&mut self.0).read(&mut buf[..n])
+        }
+    }
+
+    type Messages = Vec<(u32, String, Vec<u8>)>;
+
+    fn stream_messages(data: &[u8]) -> Result<Messages, crate::Error> {
+        let mut res = Vec::new();
+        for chunk in BagStream::new(Trickle(data))? {
+            for record in chunk?.messages() {
+                if let MessageRecord::MessageData(msg) = record? {
+                    res.push((msg.conn_id, msg.time.to_string(), msg.data.to_vec()));
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    #[test]
+    fn compressed_chunks() {
+        for compression in [Compression::None, Compression::Bzip2, Compression::Lz4] {
+            let data = write_bag(compression, 10, 4);
+            let bag = RosBag::from_bytes(&data).unwrap();
+            let expected: Messages = bag
+                .messages()
+                .map(|msg| {
+                    let msg = msg.unwrap();
+                    (msg.conn.id, msg.time.to_string(), msg.data().to_vec())
+                })
+                .collect();
+            assert_eq!(stream_messages(&data).unwrap(), expected);
+
+            let stream = BagStream::new(Trickle(&data)).unwrap();
+            assert_eq!((stream.get_conn_count(), stream.get_chunk_count()), (2, 5));
+            let chunks: Vec<_> = stream.map(|chunk| chunk.unwrap().compression).collect();
+            assert_eq!(chunks, [compression; 5]);
+            // bag without the index section
+            let stripped = &data[..bag.index_pos];
+            assert_eq!(stream_messages(stripped).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let data = write_bag(Compression::Bzip2, 10, 4);
+        let bag = RosBag::from_bytes(&data).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).max().unwrap();
+
+        let truncated = &data[..chunk_pos as usize + 50];
+        let mut stream = BagStream::new(Trickle(truncated)).unwrap();
+        for _ in 0..4 {
+            stream.next().unwrap().unwrap();
+        }
+        let err = stream.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Io);
+        assert_eq!(err.offset(), Some(chunk_pos));
+        assert!(stream.next().is_none());
+
+        // truncated bag header
+        let err = BagStream::new(Trickle(&data[..20])).err().unwrap();
+        assert_eq!(err.kind(), &ErrorKind::Io);
+        assert_eq!(err.offset(), Some(13));
+    }
+}
@@ -0,0 +1,64 @@
+//! Helpers for building bags in unit tests
+use crate::record_types::{Compression, Connection, MessageData};
+use crate::time::Time;
+use crate::BagWriter;
+use std::io::Cursor;
+
+/// Topics of connections written by [`write_bag`], connection ID is the
+/// index in this array.
+pub(crate) const TOPICS: [&str; 2] = ["/a", "/b"];
+
+pub(crate) fn connection(id: u32) -> Connection<'static> {
+    Connection {
+        id,
+        storage_topic: TOPICS[id as usize],
+        topic: TOPICS[id as usize],
+        tp: "std_msgs/String",
+        md5sum: *b"\x99\x2c\xe8\xa1\x68\x7c\xec\x8c\x8b\xd8\x83\xec\x73\xca\x41\xd1",
+        message_definition: "string data\n",
+        caller_id: "",
+        latching: false,
+    }
+}
+
+/// Serialize `std_msgs/String` message.
+pub(crate) fn string_msg(s: &str) -> Vec<u8> {
+    let mut buf = (s.len() as u32).to_le_bytes().to_vec();
+    buf.extend_from_slice(s.as_bytes());
+    buf
+}
+
+/// Write bag with `n` messages on each of [`TOPICS`].
+///
+/// Message `i` of connection `id` is received at `i` seconds plus `id`
+/// milliseconds and contains string `"{topic} {i}"`. Chunks get written
+/// after each `per_chunk` messages.
+pub(crate) fn write_bag(compression: Compression, n: u32, per_chunk: u32) -> Vec<u8> {
+    let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+    writer.set_compression(compression);
+    for id in 0..TOPICS.len() as u32 {
+        writer.add_connection(&connection(id)).unwrap();
+    }
+    let mut count = 0;
+    for i in 0..n {
+        for id in 0..TOPICS.len() as u32 {
+            let data = string_msg(&format!("{} {}", TOPICS[id as usize], i));
+            let msg = MessageData {
+                conn_id: id,
+                time: message_time(id, i),
+                data: &data,
+            };
+            writer.write_message(&msg).unwrap();
+            count += 1;
+            if count % per_chunk == 0 {
+                writer.flush_chunk().unwrap();
+            }
+        }
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Receive time of message `i` of connection `id` written by [`write_bag`].
+pub(crate) fn message_time(id: u32, i: u32) -> Time {
+    Time::new(i, id * 1_000_000)
+}
@@ -0,0 +1,507 @@
+//! ROS `time` and `duration` types
+//!
+//! Both types keep the seconds and nanoseconds stored in the bag as is,
+//! while comparison, hashing and arithmetic operate on the total number of
+//! nanoseconds, so non-normalized values (e.g. with `nsec` exceeding one
+//! second) behave consistently.
+//!
+//! ```
+//! use rosbag::time::{Duration, Time};
+//!
+//! let t = Time::new(1234, 567_890_123);
+//! assert_eq!(t.to_string(), "1234.567890123");
+//! assert_eq!(t + Duration::new(0, 500_000_000), Time::new(1235, 67_890_123));
+//! assert_eq!(Time::new(1, 0) - t, Duration::from_nanos(-1_233_567_890_123).unwrap());
+//! assert_eq!(Duration::new(-2, 500_000_000).to_string(), "-1.500000000");
+//! ```
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Neg, Sub};
+use std::{fmt, time};
+
+const NANOS: i64 = 1_000_000_000;
+
+/// Point in time stored as seconds and nanoseconds of UNIX epoch.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Time {
+    sec: u32,
+    nsec: u32,
+}
+
+/// Signed time span stored as seconds and nanoseconds.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Duration {
+    sec: i32,
+    nsec: i32,
+}
+
+/// The error type returned when a conversion to [`Time`] or [`Duration`]
+/// or between them and other types does not fit into the target type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("time value out of range")
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl Time {
+    /// UNIX epoch.
+    pub const ZERO: Time = Time { sec: 0, nsec: 0 };
+    /// The latest representable time.
+    pub const MAX: Time = Time {
+        sec: u32::MAX,
+        nsec: NANOS as u32 - 1,
+    };
+
+    /// Create time from seconds and nanoseconds of UNIX epoch.
+    pub const fn new(sec: u32, nsec: u32) -> Self {
+        Self { sec, nsec }
+    }
+
+    /// Create time from nanoseconds of UNIX epoch.
+    ///
+    /// Returns `None` if the number of seconds does not fit into `u32`.
+    pub fn from_nanos(nanos: u64) -> Option<Self> {
+        let sec = u32::try_from(nanos / NANOS as u64).ok()?;
+        let nsec = (nanos % NANOS as u64) as u32;
+        Some(Self { sec, nsec })
+    }
+
+    /// Seconds as stored in the bag.
+    pub const fn sec(&self) -> u32 {
+        self.sec
+    }
+
+    /// Nanoseconds as stored in the bag.
+    pub const fn nsec(&self) -> u32 {
+        self.nsec
+    }
+
+    /// Total number of nanoseconds of UNIX epoch.
+    pub const fn as_nanos(&self) -> u64 {
+        self.sec as u64 * NANOS as u64 + self.nsec as u64
+    }
+
+    /// Total number of seconds of UNIX epoch.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.sec as f64 + self.nsec as f64 / NANOS as f64
+    }
+
+    /// Add `duration`, returns `None` if the result is out of range.
+    pub fn checked_add(self, duration: Duration) -> Option<Time> {
+        let nanos = i128::from(self.as_nanos()) + i128::from(duration.as_nanos());
+        Time::from_nanos(u64::try_from(nanos).ok()?)
+    }
+
+    /// Subtract `duration`, returns `None` if the result is out of range.
+    pub fn checked_sub(self, duration: Duration) -> Option<Time> {
+        let nanos = i128::from(self.as_nanos()) - i128::from(duration.as_nanos());
+        Time::from_nanos(u64::try_from(nanos).ok()?)
+    }
+
+    /// Get duration elapsed from `earlier` to `self`, which is negative if
+    /// `earlier` is later than `self`.
+    ///
+    /// Returns `None` if the result is out of range.
+    pub fn checked_duration_since(self, earlier: Time) -> Option<Duration> {
+        let nanos = self.as_nanos() as i64 - earlier.as_nanos() as i64;
+        Duration::from_nanos(nanos)
+    }
+}
+
+impl Duration {
+    /// Zero duration.
+    pub const ZERO: Duration = Duration { sec: 0, nsec: 0 };
+    /// The longest representable duration.
+    pub const MAX: Duration = Duration {
+        sec: i32::MAX,
+        nsec: NANOS as i32 - 1,
+    };
+    /// The shortest representable (i.e. the most negative) duration.
+    pub const MIN: Duration = Duration {
+        sec: i32::MIN,
+        nsec: 0,
+    };
+
+    /// Create duration from seconds and nanoseconds.
+    pub const fn new(sec: i32, nsec: i32) -> Self {
+        Self { sec, nsec }
+    }
+
+    /// Create duration from nanoseconds.
+    ///
+    /// The result is normalized, i.e. its nanoseconds are in the
+    /// `0..1_000_000_000` range. Returns `None` if the number of seconds
+    /// does not fit into `i32`.
+    pub fn from_nanos(nanos: i64) -> Option<Self> {
+        let sec = i32::try_from(nanos.div_euclid(NANOS)).ok()?;
+        let nsec = nanos.rem_euclid(NANOS) as i32;
+        Some(Self { sec, nsec })
+    }
+
+    /// Seconds as stored in the bag.
+    pub const fn sec(&self) -> i32 {
+        self.sec
+    }
+
+    /// Nanoseconds as stored in the bag.
+    pub const fn nsec(&self) -> i32 {
+        self.nsec
+    }
+
+    /// Total number of nanoseconds.
+    pub const fn as_nanos(&self) -> i64 {
+        self.sec as i64 * NANOS + self.nsec as i64
+    }
+
+    /// Total number of seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.as_nanos() as f64 / NANOS as f64
+    }
+
+    /// Check if the duration is negative.
+    pub const fn is_negative(&self) -> bool {
+        self.as_nanos() < 0
+    }
+
+    /// Add `rhs`, returns `None` if the result is out of range.
+    pub fn checked_add(self, rhs: Duration) -> Option<Duration> {
+        Duration::from_nanos(self.as_nanos() + rhs.as_nanos())
+    }
+
+    /// Subtract `rhs`, returns `None` if the result is out of range.
+    pub fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+        Duration::from_nanos(self.as_nanos() - rhs.as_nanos())
+    }
+
+    /// Negate duration, returns `None` if the result is out of range.
+    pub fn checked_neg(self) -> Option<Duration> {
+        Duration::from_nanos(-self.as_nanos())
+    }
+}
+
+macro_rules! impl_cmp {
+    ($t:ty) => {
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_nanos() == other.as_nanos()
+            }
+        }
+
+        impl Eq for $t {}
+
+        impl PartialOrd for $t {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $t {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.as_nanos().cmp(&other.as_nanos())
+            }
+        }
+
+        impl Hash for $t {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.as_nanos().hash(state)
+            }
+        }
+    };
+}
+
+impl_cmp!(Time);
+impl_cmp!(Duration);
+
+impl Add<Duration> for Time {
+    type Output = Time;
+
+    fn add(self, rhs: Duration) -> Time {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to time")
+    }
+}
+
+impl Sub<Duration> for Time {
+    type Output = Time;
+
+    fn sub(self, rhs: Duration) -> Time {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from time")
+    }
+}
+
+impl Sub<Time> for Time {
+    type Output = Duration;
+
+    fn sub(self, rhs: Time) -> Duration {
+        self.checked_duration_since(rhs)
+            .expect("overflow when subtracting times")
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        self.checked_add(rhs)
+            .expect("overflow when adding durations")
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting durations")
+    }
+}
+
+impl Neg for Duration {
+    type Output = Duration;
+
+    fn neg(self) -> Duration {
+        self.checked_neg().expect("overflow when negating duration")
+    }
+}
+
+/// Formats time as seconds with nanosecond precision, e.g. `1234.567890123`.
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.as_nanos();
+        let s = format!("{}.{:09}", nanos / NANOS as u64, nanos % NANOS as u64);
+        f.pad(&s)
+    }
+}
+
+/// Formats duration as seconds with nanosecond precision, e.g.
+/// `-1.500000000`.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.as_nanos();
+        let sign = if nanos < 0 { "-" } else { "" };
+        let abs = nanos.unsigned_abs();
+        let s = format!("{}{}.{:09}", sign, abs / NANOS as u64, abs % NANOS as u64);
+        f.pad(&s)
+    }
+}
+
+impl From<Time> for time::SystemTime {
+    fn from(t: Time) -> Self {
+        time::UNIX_EPOCH + time::Duration::from_nanos(t.as_nanos())
+    }
+}
+
+impl TryFrom<time::SystemTime> for Time {
+    type Error = OutOfRange;
+
+    fn try_from(t: time::SystemTime) -> Result<Self, OutOfRange> {
+        let d = t.duration_since(time::UNIX_EPOCH).map_err(|_| OutOfRange)?;
+        let sec = u32::try_from(d.as_secs()).map_err(|_| OutOfRange)?;
+        Ok(Time::new(sec, d.subsec_nanos()))
+    }
+}
+
+impl TryFrom<Duration> for time::Duration {
+    type Error = OutOfRange;
+
+    /// Fails for negative durations.
+    fn try_from(d: Duration) -> Result<Self, OutOfRange> {
+        let nanos = u64::try_from(d.as_nanos()).map_err(|_| OutOfRange)?;
+        Ok(time::Duration::from_nanos(nanos))
+    }
+}
+
+impl TryFrom<time::Duration> for Duration {
+    type Error = OutOfRange;
+
+    fn try_from(d: time::Duration) -> Result<Self, OutOfRange> {
+        let sec = i32::try_from(d.as_secs()).map_err(|_| OutOfRange)?;
+        Ok(Duration::new(sec, d.subsec_nanos() as i32))
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use super::{Duration, OutOfRange, Time, NANOS};
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::convert::TryFrom;
+
+    impl From<Time> for DateTime<Utc> {
+        fn from(t: Time) -> Self {
+            let nanos = t.as_nanos();
+            let (sec, nsec) = (nanos / NANOS as u64, nanos % NANOS as u64);
+            Utc.timestamp_opt(sec as i64, nsec as u32)
+                .single()
+                .expect("time is within the range of chrono")
+        }
+    }
+
+    impl TryFrom<DateTime<Utc>> for Time {
+        type Error = OutOfRange;
+
+        fn try_from(t: DateTime<Utc>) -> Result<Self, OutOfRange> {
+            let sec = u64::try_from(t.timestamp()).map_err(|_| OutOfRange)?;
+            // leap seconds are represented by nanoseconds exceeding one second
+            let nanos = sec
+                .checked_mul(NANOS as u64)
+                .and_then(|n| n.checked_add(t.timestamp_subsec_nanos() as u64))
+                .ok_or(OutOfRange)?;
+            Time::from_nanos(nanos).ok_or(OutOfRange)
+        }
+    }
+
+    impl From<Duration> for chrono::Duration {
+        fn from(d: Duration) -> Self {
+            chrono::Duration::nanoseconds(d.as_nanos())
+        }
+    }
+
+    impl TryFrom<chrono::Duration> for Duration {
+        type Error = OutOfRange;
+
+        fn try_from(d: chrono::Duration) -> Result<Self, OutOfRange> {
+            let nanos = d.num_nanoseconds().ok_or(OutOfRange)?;
+            Duration::from_nanos(nanos).ok_or(OutOfRange)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::{Duration, Time};
+    use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    const FIELDS: &[&str] = &["sec", "nsec"];
+
+    /// Visitor of `{sec, nsec}` structs, as the ROS `time` and `duration`
+    /// types are represented by the [`de`][crate::de] module.
+    struct SecNsec<T>(&'static str, PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for SecNsec<T> {
+        type Value = (T, T);
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "struct {}", self.0)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(T, T), A::Error> {
+            let sec = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let nsec = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+            Ok((sec, nsec))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(T, T), A::Error> {
+            let (mut sec, mut nsec) = (None, None);
+            while let Some(key) = map.next_key::<String>()? {
+                let (name, field) = match key.as_str() {
+                    "sec" | "secs" => ("sec", &mut sec),
+                    "nsec" | "nsecs" => ("nsec", &mut nsec),
+                    _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                };
+                if field.is_some() {
+                    return Err(de::Error::duplicate_field(name));
+                }
+                *field = Some(map.next_value()?);
+            }
+            let sec = sec.ok_or_else(|| de::Error::missing_field("sec"))?;
+            let nsec = nsec.ok_or_else(|| de::Error::missing_field("nsec"))?;
+            Ok((sec, nsec))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Time {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let (sec, nsec) = d.deserialize_struct("Time", FIELDS, SecNsec("Time", PhantomData))?;
+            Ok(Time::new(sec, nsec))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Duration {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let visitor = SecNsec("Duration", PhantomData);
+            let (sec, nsec) = d.deserialize_struct("Duration", FIELDS, visitor)?;
+            Ok(Duration::new(sec, nsec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duration, Time};
+    use std::convert::TryFrom;
+    use std::time;
+
+    const NS: Duration = Duration::new(0, 1);
+
+    #[test]
+    fn time_overflow() {
+        assert_eq!(Time::MAX.checked_add(NS), None);
+        assert_eq!(Time::ZERO.checked_sub(NS), None);
+        assert_eq!(Time::ZERO.checked_add(Duration::MIN), None);
+        assert_eq!(
+            Time::MAX.checked_sub(NS),
+            Time::from_nanos(Time::MAX.as_nanos() - 1)
+        );
+        assert_eq!(Time::from_nanos(u64::MAX), None);
+        assert_eq!(Time::from_nanos(Time::MAX.as_nanos()), Some(Time::MAX));
+        // not normalized time beyond `Time::MAX`
+        let t = Time::new(u32::MAX, u32::MAX);
+        assert!(t > Time::MAX);
+        assert_eq!(t.checked_add(Duration::ZERO), None);
+        // the span does not fit into `Duration`
+        assert_eq!(Time::MAX.checked_duration_since(Time::ZERO), None);
+        assert_eq!(Time::ZERO.checked_duration_since(Time::MAX), None);
+        let span = Time::new(1, 0).checked_duration_since(Time::new(2, 500_000_000));
+        assert_eq!(span, Some(Duration::new(-2, 500_000_000)));
+
+        let before_epoch = time::UNIX_EPOCH - time::Duration::from_nanos(1);
+        assert!(Time::try_from(before_epoch).is_err());
+    }
+
+    #[test]
+    fn duration_overflow() {
+        assert_eq!(Duration::MAX.checked_add(NS), None);
+        assert_eq!(Duration::MIN.checked_sub(NS), None);
+        assert_eq!(Duration::MIN.checked_neg(), None);
+        assert_eq!(Duration::MAX.checked_neg(), Duration::MIN.checked_add(NS));
+        assert_eq!(Duration::from_nanos(i64::MAX), None);
+        assert_eq!(Duration::from_nanos(i64::MIN), None);
+        assert_eq!(
+            Duration::from_nanos(-1),
+            Some(Duration::new(-1, 999_999_999))
+        );
+        assert_eq!(
+            Duration::new(1, 1_500_000_000),
+            Duration::new(2, 500_000_000)
+        );
+
+        assert!(time::Duration::try_from(Duration::new(0, -1)).is_err());
+        let max = time::Duration::new(i32::MAX as u64 + 1, 0);
+        assert!(Duration::try_from(max).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow when adding duration to time")]
+    fn time_add_panics() {
+        let _ = Time::MAX + NS;
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow when subtracting durations")]
+    fn duration_sub_panics() {
+        let _ = Duration::MIN - NS;
+    }
+}
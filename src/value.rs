@@ -0,0 +1,415 @@
+//! Dynamic decoding of messages serialized in the ROS serialization format.
+//!
+//! # Example
+//! ```
+//! use rosbag::schema::MessageSchema;
+//! use rosbag::value::{MessageDecoder, Value};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let schema = MessageSchema::parse("foo_msgs/Foo", "string name\nint16[] data\n")?;
+//! let decoder = MessageDecoder::new(schema);
+//! let data = b"\x03\x00\x00\x00foo\x02\x00\x00\x00\x01\x00\xff\xff";
+//! let value = decoder.decode(data)?;
+//! assert_eq!(value.get("name"), Some(&Value::String("foo".into())));
+//! assert_eq!(
+//!     value.get("data"),
+//!     Some(&Value::Array(vec![Value::I16(1), Value::I16(-1)])),
+//! );
+//! # Ok(()) }
+//! ```
+use crate::cursor::Cursor;
+use crate::record_types::Connection;
+use crate::schema::{FieldType, MessageDefinition, MessageSchema, PrimitiveType};
+use crate::time::{Duration, Time};
+use crate::{Error, ErrorKind, Result};
+use byteorder::{ByteOrder, LE};
+use std::collections::{HashMap, HashSet};
+
+/// Dynamically typed value of a decoded message or its field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `bool` value.
+    Bool(bool),
+    /// `int8` or `byte` value.
+    I8(i8),
+    /// `uint8` or `char` value.
+    U8(u8),
+    /// `int16` value.
+    I16(i16),
+    /// `uint16` value.
+    U16(u16),
+    /// `int32` value.
+    I32(i32),
+    /// `uint32` value.
+    U32(u32),
+    /// `int64` value.
+    I64(i64),
+    /// `uint64` value.
+    U64(u64),
+    /// `float32` value.
+    F32(f32),
+    /// `float64` value.
+    F64(f64),
+    /// `string` value. Invalid UTF-8 sequences get replaced with `U+FFFD`.
+    String(String),
+    /// `time` value.
+    Time(Time),
+    /// `duration` value.
+    Duration(Duration),
+    /// Array of `uint8` or `char` values.
+    Bytes(Vec<u8>),
+    /// Array of values.
+    Array(Vec<Value>),
+    /// Nested message with field names and values in the serialization order.
+    Message(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get field of a message value by its name.
+    ///
+    /// Returns `None` if value is not a message or if it does not have
+    /// the field.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Message(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Decoder of serialized messages into [`Value`]s based on a message schema.
+#[derive(Debug, Clone)]
+pub struct MessageDecoder {
+    root: MessageDefinition,
+    dependencies: HashMap<String, MessageDefinition>,
+    /// Names of message types which serialize into zero bytes
+    zero_sized: HashSet<String>,
+}
+
+impl MessageDecoder {
+    /// Create a new decoder for the provided schema.
+    pub fn new(schema: MessageSchema) -> Self {
+        let dependencies = schema
+            .dependencies
+            .into_iter()
+            .map(|def| (def.name.clone(), def))
+            .collect();
+        let mut decoder = Self {
+            root: schema.root,
+            dependencies,
+            zero_sized: HashSet::new(),
+        };
+        // add types until the set stops changing, so that types in cycles
+        // of manually built schemas are not considered zero-sized
+        loop {
+            let found: Vec<String> = decoder
+                .definitions()
+                .filter(|def| !decoder.zero_sized.contains(&def.name))
+                .filter(|def| def.fields.iter().all(|f| decoder.is_zero_sized(&f.tp)))
+                .map(|def| def.name.clone())
+                .collect();
+            if found.is_empty() {
+                break decoder;
+            }
+            decoder.zero_sized.extend(found);
+        }
+    }
+
+    /// Create a new decoder for messages of the connection.
+    pub fn from_connection(conn: &Connection<'_>) -> Result<Self> {
+        Ok(Self::new(conn.message_schema()?))
+    }
+
+    /// Decode serialized message (e.g. [`MessageData::data`][crate::record_types::MessageData]).
+    pub fn decode(&self, data: &[u8]) -> Result<Value> {
+        let mut c = Cursor::new(data);
+        let value = self.decode_message(&mut c, &self.root, 0)?;
+        if c.left() != 0 {
+            return Err(Error::new(ErrorKind::InvalidMessageData(format!(
+                "{} trailing bytes after {} message",
+                c.left(),
+                self.root.name,
+            ))));
+        }
+        Ok(value)
+    }
+
+    fn decode_message(
+        &self,
+        c: &mut Cursor<'_>,
+        def: &MessageDefinition,
+        depth: usize,
+    ) -> Result<Value> {
+        // without recursion nesting depth is bounded by the number of types
+        if depth > self.dependencies.len() {
+            return Err(Error::new(ErrorKind::InvalidMessageDefinition(format!(
+                "recursive definition of {}",
+                def.name
+            ))));
+        }
+        let mut fields = Vec::with_capacity(def.fields.len());
+        for field in def.fields.iter() {
+            let value = self
+                .decode_field(c, &field.tp, depth)
+                .map_err(|e| e.in_field(&field.name))?;
+            fields.push((field.name.clone(), value));
+        }
+        Ok(Value::Message(fields))
+    }
+
+    fn decode_field(&self, c: &mut Cursor<'_>, tp: &FieldType, depth: usize) -> Result<Value> {
+        Ok(match tp {
+            FieldType::Primitive(tp) => decode_primitive(c, *tp)?,
+            FieldType::Message(name) => {
+                let def = self.get_definition(name)?;
+                self.decode_message(c, def, depth + 1)?
+            }
+            FieldType::Array { tp, len } => {
+                let len = match len {
+                    Some(n) => *n as u64,
+                    None => c.next_u32()? as u64,
+                };
+                match **tp {
+                    FieldType::Primitive(PrimitiveType::UInt8)
+                    | FieldType::Primitive(PrimitiveType::Char) => {
+                        Value::Bytes(c.next_bytes(len)?.to_vec())
+                    }
+                    _ if len != 0 && self.is_zero_sized(tp) => {
+                        return Err(Error::new(ErrorKind::InvalidMessageData(format!(
+                            "array of {} zero-sized {} elements",
+                            len, tp
+                        ))));
+                    }
+                    _ => {
+                        // every element takes at least one byte
+                        let mut values = Vec::with_capacity(len.min(c.left()) as usize);
+                        for _ in 0..len {
+                            values.push(self.decode_field(c, tp, depth)?);
+                        }
+                        Value::Array(values)
+                    }
+                }
+            }
+        })
+    }
+
+    fn definitions(&self) -> impl Iterator<Item = &MessageDefinition> {
+        std::iter::once(&self.root).chain(self.dependencies.values())
+    }
+
+    /// Check if values of the type serialize into zero bytes.
+    fn is_zero_sized(&self, tp: &FieldType) -> bool {
+        match tp {
+            FieldType::Primitive(_) => false,
+            FieldType::Message(name) => self.zero_sized.contains(name),
+            FieldType::Array { tp, len: Some(n) } => *n == 0 || self.is_zero_sized(tp),
+            FieldType::Array { len: None, .. } => false,
+        }
+    }
+
+    fn get_definition(&self, name: &str) -> Result<&MessageDefinition> {
+        if self.root.name == name {
+            return Ok(&self.root);
+        }
+        self.dependencies.get(name).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidMessageDefinition(format!(
+                "unknown type: {}",
+                name
+            )))
+        })
+    }
+}
+
+fn decode_primitive(c: &mut Cursor<'_>, tp: PrimitiveType) -> Result<Value> {
+    use PrimitiveType::*;
+    Ok(match tp {
+        Bool => Value::Bool(c.next_bytes(1)?[0] != 0),
+        Int8 | Byte => Value::I8(c.next_bytes(1)?[0] as i8),
+        UInt8 | Char => Value::U8(c.next_bytes(1)?[0]),
+        Int16 => Value::I16(LE::read_i16(c.next_bytes(2)?)),
+        UInt16 => Value::U16(LE::read_u16(c.next_bytes(2)?)),
+        Int32 => Value::I32(LE::read_i32(c.next_bytes(4)?)),
+        UInt32 => Value::U32(c.next_u32()?),
+        Int64 => Value::I64(LE::read_i64(c.next_bytes(8)?)),
+        UInt64 => Value::U64(LE::read_u64(c.next_bytes(8)?)),
+        Float32 => Value::F32(LE::read_f32(c.next_bytes(4)?)),
+        Float64 => Value::F64(LE::read_f64(c.next_bytes(8)?)),
+        String => Value::String(std::string::String::from_utf8_lossy(c.next_chunk()?).into_owned()),
+        Time => Value::Time(c.next_time()?),
+        Duration => Value::Duration(c.next_duration()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageDecoder, Value};
+    use crate::schema::{Field, FieldType, MessageDefinition, MessageSchema};
+    use crate::time::{Duration, Time};
+    use crate::ErrorKind;
+
+    fn decoder(text: &str) -> MessageDecoder {
+        MessageDecoder::new(MessageSchema::parse("a/A", text).unwrap())
+    }
+
+    fn message(fields: Vec<(&str, Value)>) -> Value {
+        Value::Message(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Serialize string with its length prefix.
+    fn string(s: &str) -> Vec<u8> {
+        [&(s.len() as u32).to_le_bytes()[..], s.as_bytes()].concat()
+    }
+
+    #[test]
+    fn primitives() {
+        let text = "bool a\nint8 b\nuint8 c\nbyte d\nchar e\nint16 f\nuint16 g\n\
+            int32 h\nuint32 i\nint64 j\nuint64 k\nfloat32 l\nfloat64 m\n";
+        let data = [
+            &[1, 0xfe, 0xfe, 0xfe, 0xfe][..],
+            &(-2i16).to_le_bytes(),
+            &0xfffeu16.to_le_bytes(),
+            &(-3i32).to_le_bytes(),
+            &0xffff_fffdu32.to_le_bytes(),
+            &(-4i64).to_le_bytes(),
+            &u64::MAX.to_le_bytes(),
+            &1.5f32.to_le_bytes(),
+            &(-0.25f64).to_le_bytes(),
+        ]
+        .concat();
+        let expected = message(vec![
+            ("a", Value::Bool(true)),
+            ("b", Value::I8(-2)),
+            ("c", Value::U8(0xfe)),
+            ("d", Value::I8(-2)),
+            ("e", Value::U8(0xfe)),
+            ("f", Value::I16(-2)),
+            ("g", Value::U16(0xfffe)),
+            ("h", Value::I32(-3)),
+            ("i", Value::U32(0xffff_fffd)),
+            ("j", Value::I64(-4)),
+            ("k", Value::U64(u64::MAX)),
+            ("l", Value::F32(1.5)),
+            ("m", Value::F64(-0.25)),
+        ]);
+        assert_eq!(decoder(text).decode(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn strings_and_times() {
+        let decoder = decoder("string s\nstring empty\ntime t\nduration d\n");
+        let data = [
+            &string("héllo")[..],
+            &string(""),
+            &[1, 0, 0, 0, 2, 0, 0, 0],
+            &(-1i32).to_le_bytes(),
+            &500_000_000i32.to_le_bytes(),
+        ]
+        .concat();
+        let expected = message(vec![
+            ("s", Value::String("héllo".into())),
+            ("empty", Value::String("".into())),
+            ("t", Value::Time(Time::new(1, 2))),
+            ("d", Value::Duration(Duration::new(-1, 500_000_000))),
+        ]);
+        assert_eq!(decoder.decode(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn arrays() {
+        let text = "int16[2] fixed\nuint8[] bytes\nchar[3] chars\nstring[] strings\n\
+            float64[] empty\n";
+        let data = [
+            &[1, 0, 0xff, 0xff][..],
+            &[2, 0, 0, 0, 7, 8],
+            b"abc",
+            &[2, 0, 0, 0],
+            &string("x"),
+            &string("yz"),
+            &[0, 0, 0, 0],
+        ]
+        .concat();
+        let expected = message(vec![
+            ("fixed", Value::Array(vec![Value::I16(1), Value::I16(-1)])),
+            ("bytes", Value::Bytes(vec![7, 8])),
+            ("chars", Value::Bytes(b"abc".to_vec())),
+            (
+                "strings",
+                Value::Array(vec![Value::String("x".into()), Value::String("yz".into())]),
+            ),
+            ("empty", Value::Array(vec![])),
+        ]);
+        assert_eq!(decoder(text).decode(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn nested_messages() {
+        let text = "Header header\nPoint[] points\nPoint[1] origin\n\
+            ===\nMSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n\
+            ===\nMSG: a/Point\nint8 x\nint8 y\n";
+        let data = [
+            &[5, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0][..],
+            &string("map"),
+            &[2, 0, 0, 0, 1, 2, 3, 4],
+            &[0xff, 0xfe],
+        ]
+        .concat();
+        let point = |x, y| message(vec![("x", Value::I8(x)), ("y", Value::I8(y))]);
+        let header = message(vec![
+            ("seq", Value::U32(5)),
+            ("stamp", Value::Time(Time::new(1, 2))),
+            ("frame_id", Value::String("map".into())),
+        ]);
+        let expected = message(vec![
+            ("header", header),
+            ("points", Value::Array(vec![point(1, 2), point(3, 4)])),
+            ("origin", Value::Array(vec![point(-1, -2)])),
+        ]);
+        let decoder = decoder(text);
+        let value = decoder.decode(&data).unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(
+            value.get("header").and_then(|h| h.get("frame_id")),
+            Some(&Value::String("map".into()))
+        );
+
+        // truncated and trailing data
+        let err = decoder.decode(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::OutOfBounds);
+        assert_eq!(err.field(), Some("y"));
+        let err = decoder.decode(&[&data[..], &[0]].concat()).unwrap_err();
+        let msg = "1 trailing bytes after a/A message".to_string();
+        assert_eq!(err.kind(), &ErrorKind::InvalidMessageData(msg));
+    }
+
+    #[test]
+    fn recursive_schema() {
+        // recursive schemas are rejected by the parser, but can be built manually
+        let root = MessageDefinition {
+            name: "a/A".into(),
+            fields: vec![Field {
+                name: "next".into(),
+                tp: FieldType::Message("a/A".into()),
+            }],
+            constants: vec![],
+        };
+        let schema = MessageSchema {
+            root,
+            dependencies: vec![],
+        };
+        let err = MessageDecoder::new(schema).decode(&[]).unwrap_err();
+        let msg = "recursive definition of a/A".to_string();
+        assert_eq!(err.kind(), &ErrorKind::InvalidMessageDefinition(msg));
+    }
+
+    #[test]
+    fn zero_sized_arrays() {
+        let text = "B[] b\n===\nMSG: a/B\nC[3] c\nint8[0] d\n===\nMSG: a/C\n";
+        let decoder = MessageDecoder::new(MessageSchema::parse("a/A", text).unwrap());
+        let empty = Value::Message(vec![("b".into(), Value::Array(vec![]))]);
+        assert_eq!(decoder.decode(&[0, 0, 0, 0]).unwrap(), empty);
+        let err = decoder.decode(&[0xff, 0xff, 0xff, 0xff]).unwrap_err();
+        let msg = "array of 4294967295 zero-sized a/B elements".to_string();
+        assert_eq!(err.kind(), &ErrorKind::InvalidMessageData(msg));
+        assert_eq!(err.field(), Some("b"));
+    }
+}
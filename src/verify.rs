@@ -0,0 +1,509 @@
+//! Integrity check which cross-validates the index section and chunk contents
+use crate::cursor::Cursor;
+use crate::msg_iter::{MessageRecord, MessageRecordsIterator};
+use crate::record::read_op;
+use crate::record_types::{Chunk, ChunkInfo, IndexData, RawChunk, RecordGen};
+use crate::time::Time;
+use crate::{Error, ErrorKind, RosBag, VERSION_LEN};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Result of [`RosBag::verify`].
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// Found problems sorted by their position in the file
+    pub issues: Vec<Issue>,
+    /// Number of `Chunk` records found in the chunk section
+    pub chunk_count: u64,
+    /// Number of messages found in the chunks
+    pub message_count: u64,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in self.issues.iter() {
+            writeln!(f, "{}", issue)?;
+        }
+        write!(
+            f,
+            "checked {} chunks and {} messages, found {} issues",
+            self.chunk_count,
+            self.message_count,
+            self.issues.len(),
+        )
+    }
+}
+
+/// Problem found by [`RosBag::verify`].
+#[derive(Debug)]
+pub struct Issue {
+    /// Position of the affected record relative to the file beginning
+    pub pos: u64,
+    /// Kind of the problem
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pos, self.kind)
+    }
+}
+
+/// Kind of problem found by [`RosBag::verify`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IssueKind {
+    /// Record in the chunk section can not be read, the rest of the chunk
+    /// section is not checked.
+    InvalidRecord(Error),
+    /// Chunk data can not be decompressed or contains invalid records.
+    InvalidChunk(Error),
+    /// Record in the index section can not be read, records following it
+    /// are ignored.
+    InvalidIndexRecord(Error),
+    /// `conn_count` in the bag header does not match number of `Connection`
+    /// records in the index section.
+    ConnCountMismatch {
+        /// Value stored in the bag header
+        header: u32,
+        /// Number of `Connection` records
+        actual: u32,
+    },
+    /// `chunk_count` in the bag header does not match number of `Chunk`
+    /// records in the chunk section.
+    ChunkCountMismatch {
+        /// Value stored in the bag header
+        header: u32,
+        /// Number of `Chunk` records
+        actual: u32,
+    },
+    /// `ChunkInfo::chunk_pos` does not point at a `Chunk` record.
+    InvalidChunkPos {
+        /// Stored chunk position
+        chunk_pos: u64,
+    },
+    /// `Chunk` record is not described by any `ChunkInfo` record.
+    UnindexedChunk,
+    /// `ChunkInfoEntry::count` does not match number of messages in the chunk.
+    MessageCountMismatch {
+        /// Connection ID
+        conn_id: u32,
+        /// Count stored in the `ChunkInfo` record
+        expected: u32,
+        /// Number of messages in the chunk
+        actual: u32,
+    },
+    /// Receive times of messages in the chunk are not bracketed by
+    /// `ChunkInfo` start and end times.
+    TimeRangeMismatch {
+        /// Start time stored in the `ChunkInfo` record
+        start_time: Time,
+        /// End time stored in the `ChunkInfo` record
+        end_time: Time,
+        /// Receive time of the earliest message in the chunk
+        first: Time,
+        /// Receive time of the latest message in the chunk
+        last: Time,
+    },
+    /// Chunk contains messages of connection without `Connection` record in
+    /// the index section.
+    UnknownConnection {
+        /// Connection ID
+        conn_id: u32,
+    },
+    /// Chunk is not followed by `IndexData` record for a connection which
+    /// messages it contains.
+    MissingIndexData {
+        /// Connection ID
+        conn_id: u32,
+    },
+    /// Number of `IndexData` entries does not match number of messages in
+    /// the chunk.
+    IndexCountMismatch {
+        /// Connection ID
+        conn_id: u32,
+        /// Number of entries in the `IndexData` record
+        expected: u32,
+        /// Number of messages in the chunk
+        actual: u32,
+    },
+    /// `IndexData` entry does not point at a `MessageData` record with the
+    /// same connection and time.
+    InvalidIndexEntry {
+        /// Connection ID
+        conn_id: u32,
+        /// Time stored in the entry
+        time: Time,
+        /// Offset stored in the entry
+        offset: u32,
+    },
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IssueKind::*;
+        match self {
+            InvalidRecord(err) => write!(f, "invalid record: {}", err),
+            InvalidChunk(err) => write!(f, "invalid chunk: {}", err),
+            InvalidIndexRecord(err) => write!(f, "invalid index record: {}", err),
+            ConnCountMismatch { header, actual } => write!(
+                f,
+                "bag header conn_count is {}, but index has {} connections",
+                header, actual
+            ),
+            ChunkCountMismatch { header, actual } => write!(
+                f,
+                "bag header chunk_count is {}, but bag has {} chunks",
+                header, actual
+            ),
+            InvalidChunkPos { chunk_pos } => {
+                write!(f, "chunk_pos {} does not point at a chunk", chunk_pos)
+            }
+            UnindexedChunk => write!(f, "chunk is not listed in the index"),
+            MessageCountMismatch {
+                conn_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "chunk info lists {} messages of connection {}, but chunk has {}",
+                expected, conn_id, actual
+            ),
+            TimeRangeMismatch {
+                start_time,
+                end_time,
+                first,
+                last,
+            } => write!(
+                f,
+                "chunk info time range {}..={} does not bracket messages in {}..={}",
+                start_time, end_time, first, last
+            ),
+            UnknownConnection { conn_id } => {
+                write!(f, "chunk has messages of unknown connection {}", conn_id)
+            }
+            MissingIndexData { conn_id } => {
+                write!(f, "chunk has no index data for connection {}", conn_id)
+            }
+            IndexCountMismatch {
+                conn_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "index data has {} entries for connection {}, but chunk has {} messages",
+                expected, conn_id, actual
+            ),
+            InvalidIndexEntry {
+                conn_id,
+                time,
+                offset,
+            } => write!(
+                f,
+                "index entry of connection {} with time {} and offset {} \
+                does not point at a matching message",
+                conn_id, time, offset
+            ),
+        }
+    }
+}
+
+/// Messages found in a chunk.
+struct ChunkContents {
+    pos: u64,
+    /// `(conn_id, time)` of messages by their offset
+    messages: HashMap<u32, (u32, Time)>,
+    counts: BTreeMap<u32, u32>,
+    first: Time,
+    last: Time,
+}
+
+struct Verifier<'a, D> {
+    bag: &'a RosBag<D>,
+    issues: Vec<Issue>,
+    /// `ChunkInfo` records and their positions by chunk position
+    chunk_infos: HashMap<u64, (u64, ChunkInfo<'a>)>,
+    /// Positions of found `Chunk` records
+    chunks: HashSet<u64>,
+    message_count: u64,
+}
+
+impl<'a, D: AsRef<[u8]>> Verifier<'a, D> {
+    fn issue(&mut self, pos: u64, kind: IssueKind) {
+        self.issues.push(Issue { pos, kind });
+    }
+
+    fn walk_chunk_section(&mut self) {
+        let bag = self.bag;
+        let mut c = Cursor::new(&bag.bytes()[..bag.index_pos]);
+        c.seek(bag.start_pos as u64)
+            .expect("start position is in bounds");
+
+        // contents of the last chunk and following `IndexData` records
+        let mut current: Option<ChunkContents> = None;
+        let mut index: Vec<(u64, IndexData<'a>)> = Vec::new();
+        while c.left() != 0 {
+            let pos = c.pos();
+            let header = match c.next_chunk() {
+                Ok(header) => header,
+                Err(err) => {
+                    self.issue(pos, IssueKind::InvalidRecord(err.into()));
+                    break;
+                }
+            };
+            match read_op(header) {
+                Ok(Some(Chunk::OP)) => {
+                    if let Some(chunk) = current.take() {
+                        self.check_chunk(chunk, &index);
+                    }
+                    index.clear();
+                    let raw = match RawChunk::read_with_header(header, &mut c) {
+                        Ok(raw) => raw,
+                        Err(err) => {
+                            self.issue(pos, IssueKind::InvalidRecord(err));
+                            break;
+                        }
+                    };
+                    self.chunks.insert(pos);
+                    match read_chunk(pos, raw) {
+                        Ok(chunk) => current = Some(chunk),
+                        Err(err) => self.issue(pos, IssueKind::InvalidChunk(err)),
+                    }
+                }
+                Ok(Some(IndexData::OP)) => match IndexData::read(header, &mut c) {
+                    Ok(data) => index.push((pos, data)),
+                    Err(err) => {
+                        self.issue(pos, IssueKind::InvalidRecord(err));
+                        break;
+                    }
+                },
+                Ok(_) => {
+                    self.issue(
+                        pos,
+                        IssueKind::InvalidRecord(Error::new(ErrorKind::InvalidRecord)),
+                    );
+                    break;
+                }
+                Err(err) => {
+                    self.issue(pos, IssueKind::InvalidRecord(err));
+                    break;
+                }
+            }
+        }
+        if let Some(chunk) = current.take() {
+            self.check_chunk(chunk, &index);
+        }
+    }
+
+    fn check_chunk(&mut self, chunk: ChunkContents, index: &[(u64, IndexData<'a>)]) {
+        let pos = chunk.pos;
+        self.message_count += chunk.messages.len() as u64;
+        for &conn_id in chunk.counts.keys() {
+            if !self.bag.index.connections.contains_key(&conn_id) {
+                self.issue(pos, IssueKind::UnknownConnection { conn_id });
+            }
+        }
+
+        match self.chunk_infos.get(&pos) {
+            Some((info_pos, info)) => {
+                let (info_pos, start_time, end_time) = (*info_pos, info.start_time, info.end_time);
+                let mut expected: BTreeMap<u32, u32> =
+                    info.entries().map(|e| (e.conn_id, e.count)).collect();
+                for &conn_id in chunk.counts.keys() {
+                    expected.entry(conn_id).or_insert(0);
+                }
+                for (conn_id, expected) in expected {
+                    let actual = chunk.counts.get(&conn_id).copied().unwrap_or(0);
+                    if expected != actual {
+                        let kind = IssueKind::MessageCountMismatch {
+                            conn_id,
+                            expected,
+                            actual,
+                        };
+                        self.issue(info_pos, kind);
+                    }
+                }
+                let empty = chunk.messages.is_empty();
+                if !empty && (chunk.first < start_time || chunk.last > end_time) {
+                    let kind = IssueKind::TimeRangeMismatch {
+                        start_time,
+                        end_time,
+                        first: chunk.first,
+                        last: chunk.last,
+                    };
+                    self.issue(info_pos, kind);
+                }
+            }
+            None => self.issue(pos, IssueKind::UnindexedChunk),
+        }
+
+        let mut indexed = HashSet::new();
+        for (index_pos, data) in index {
+            let conn_id = data.conn_id;
+            indexed.insert(conn_id);
+            let expected = data.entries().count() as u32;
+            let actual = chunk.counts.get(&conn_id).copied().unwrap_or(0);
+            if expected != actual {
+                let kind = IssueKind::IndexCountMismatch {
+                    conn_id,
+                    expected,
+                    actual,
+                };
+                self.issue(*index_pos, kind);
+            }
+            for e in data.entries() {
+                if chunk.messages.get(&e.offset) != Some(&(conn_id, e.time)) {
+                    let kind = IssueKind::InvalidIndexEntry {
+                        conn_id,
+                        time: e.time,
+                        offset: e.offset,
+                    };
+                    self.issue(*index_pos, kind);
+                }
+            }
+        }
+        for &conn_id in chunk.counts.keys() {
+            if !indexed.contains(&conn_id) {
+                self.issue(pos, IssueKind::MissingIndexData { conn_id });
+            }
+        }
+    }
+}
+
+/// Decompress chunk and collect its messages.
+fn read_chunk(pos: u64, raw: RawChunk<'_>) -> Result<ChunkContents, Error> {
+    let chunk = raw.decompress()?;
+    let mut res = ChunkContents {
+        pos,
+        messages: HashMap::new(),
+        counts: BTreeMap::new(),
+        first: Time::MAX,
+        last: Time::ZERO,
+    };
+    let mut records = MessageRecordsIterator::new(&chunk.data);
+    loop {
+        let offset = records.cursor.pos() as u32;
+        match records.next() {
+            Some(Ok(MessageRecord::MessageData(msg))) => {
+                res.messages.insert(offset, (msg.conn_id, msg.time));
+                *res.counts.entry(msg.conn_id).or_default() += 1;
+                res.first = res.first.min(msg.time);
+                res.last = res.last.max(msg.time);
+            }
+            Some(Ok(MessageRecord::Connection(_))) => {}
+            Some(Err(err)) => return Err(err),
+            None => break,
+        }
+    }
+    Ok(res)
+}
+
+pub(crate) fn verify<D: AsRef<[u8]>>(bag: &RosBag<D>) -> VerifyReport {
+    let chunk_infos = bag
+        .index
+        .chunk_infos
+        .iter()
+        .map(|(pos, info)| (info.chunk_pos, (*pos, info.as_borrowed())))
+        .collect();
+    let mut v = Verifier {
+        bag,
+        issues: Vec::new(),
+        chunk_infos,
+        chunks: HashSet::new(),
+        message_count: 0,
+    };
+    if let (Some(pos), Some(err)) = (bag.index.error_pos, bag.index_error()) {
+        v.issue(pos, IssueKind::InvalidIndexRecord(err));
+    }
+    v.walk_chunk_section();
+
+    let mut infos: Vec<(u64, u64)> = v
+        .chunk_infos
+        .iter()
+        .map(|(&chunk_pos, (pos, _))| (*pos, chunk_pos))
+        .collect();
+    infos.sort_unstable();
+    for (pos, chunk_pos) in infos {
+        if !v.chunks.contains(&chunk_pos) {
+            v.issue(pos, IssueKind::InvalidChunkPos { chunk_pos });
+        }
+    }
+
+    let actual = bag.index.connections.len() as u32;
+    if bag.conn_count != actual {
+        let kind = IssueKind::ConnCountMismatch {
+            header: bag.conn_count,
+            actual,
+        };
+        v.issue(VERSION_LEN, kind);
+    }
+    let actual = v.chunks.len() as u32;
+    if bag.chunk_count != actual {
+        let kind = IssueKind::ChunkCountMismatch {
+            header: bag.chunk_count,
+            actual,
+        };
+        v.issue(VERSION_LEN, kind);
+    }
+
+    let mut issues = v.issues;
+    issues.sort_by_key(|issue| issue.pos);
+    VerifyReport {
+        issues,
+        chunk_count: v.chunks.len() as u64,
+        message_count: v.message_count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueKind;
+    use crate::record_types::Compression;
+    use crate::test_utils::write_bag;
+    use crate::{ErrorKind, RosBag};
+
+    #[test]
+    fn corrupted_chunk() {
+        let mut data = write_bag(Compression::Bzip2, 4, 4);
+        assert!(RosBag::from_bytes(data.clone()).unwrap().verify().is_ok());
+        let pos = data.windows(3).position(|w| w == b"BZh").unwrap();
+        // overwrite magic of the first compressed block
+        data[pos + 4..pos + 10].fill(0);
+        let bag = RosBag::from_bytes(data).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).min().unwrap();
+        let report = bag.verify();
+        assert_eq!((report.chunk_count, report.message_count), (2, 4));
+        assert_eq!(report.issues.len(), 1, "{}", report);
+        assert_eq!(report.issues[0].pos, chunk_pos);
+        match &report.issues[0].kind {
+            IssueKind::InvalidChunk(err) => {
+                assert_eq!(err.kind(), &ErrorKind::Decompression(Compression::Bzip2));
+            }
+            kind => panic!("unexpected issue: {}", kind),
+        }
+    }
+
+    #[test]
+    fn truncated_index_data() {
+        let data = write_bag(Compression::Lz4, 40, 40);
+        let bag = RosBag::from_bytes(data.clone()).unwrap();
+        let chunk_pos = bag.chunk_infos().map(|info| info.chunk_pos).max().unwrap();
+        // keep the last chunk with the first of its `IndexData` records
+        let bag = RosBag::reindex_bytes(data[..chunk_pos as usize + 1200].to_vec()).unwrap();
+        let report = bag.verify();
+        assert_eq!((report.chunk_count, report.message_count), (2, 80));
+        assert_eq!(report.issues.len(), 1, "{}", report);
+        assert_eq!(report.issues[0].pos, chunk_pos);
+        match report.issues[0].kind {
+            IssueKind::MissingIndexData { conn_id: 1 } => {}
+            ref kind => panic!("unexpected issue: {}", kind),
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Writer for ROS bag files.
+use crate::record_types::utils::{push_field, push_record, time_to_bytes};
+use crate::record_types::{
+    Chunk, ChunkInfo, Compression, Connection, IndexData, MessageData, RecordGen,
+};
+use crate::time::Time;
+use crate::{Error, ErrorKind, Result, ROSBAG_HEADER_OP, VERSION_STRING};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Length of the bag header record (excluding length prefixes), which gets
+/// padded so it can be rewritten in place after the index section is known.
+const BAG_HEADER_LEN: usize = 4096;
+/// Default chunk size threshold used by `rosbag record`.
+const DEFAULT_CHUNK_THRESHOLD: usize = 768 * 1024;
+
+/// Connection registered in the writer.
+struct ConnectionEntry {
+    /// Encoded `Connection` record
+    record: Vec<u8>,
+    /// Was the connection record written into the current chunk?
+    in_chunk: bool,
+}
+
+/// Information about a chunk already written into the file.
+struct ChunkEntry {
+    pos: u64,
+    start_time: Time,
+    end_time: Time,
+    counts: BTreeMap<u32, u32>,
+}
+
+/// Writer which produces ROS bag v2.0 files.
+///
+/// Messages are buffered into chunks, each of which is followed by
+/// per-connection `IndexData` records. The index section and the final bag
+/// header get written by [`BagWriter::finish`].
+///
+/// # Example
+/// ```
+/// use rosbag::record_types::{Connection, MessageData};
+/// use rosbag::time::Time;
+/// use rosbag::{BagWriter, ChunkRecord, RosBag};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let path = std::env::temp_dir().join("rosbag_writer_example.bag");
+/// let mut writer = BagWriter::create(&path)?;
+/// writer.add_connection(&Connection {
+///     id: 0,
+///     storage_topic: "/chatter",
+///     topic: "/chatter",
+///     tp: "std_msgs/String",
+///     md5sum: [
+///         0x99, 0x2c, 0xe8, 0xa1, 0x68, 0x7c, 0xec, 0x8c,
+///         0x8b, 0xd8, 0x83, 0xec, 0x73, 0xca, 0x41, 0xd1,
+///     ],
+///     message_definition: "string data\n",
+///     caller_id: "",
+///     latching: false,
+/// })?;
+/// writer.write_message(&MessageData {
+///     conn_id: 0,
+///     time: Time::new(1_500_000_000, 0),
+///     data: b"\x05\x00\x00\x00hello",
+/// })?;
+/// writer.finish()?;
+///
+/// let bag = RosBag::new(&path)?;
+/// assert_eq!(bag.get_conn_count(), 1);
+/// assert_eq!(bag.get_chunk_count(), 1);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(()) }
+/// ```
+pub struct BagWriter<W: Write + Seek> {
+    writer: Option<W>,
+    /// Position of the bag beginning in the underlying writer
+    start: u64,
+    /// Current position relative to the bag beginning
+    pos: u64,
+    compression: Compression,
+    chunk_threshold: usize,
+    /// Maximum size of uncompressed chunk data, which is stored as `u32`
+    max_chunk_len: usize,
+    connections: BTreeMap<u32, ConnectionEntry>,
+    chunks: Vec<ChunkEntry>,
+    /// Uncompressed data of the current chunk
+    chunk_data: Vec<u8>,
+    /// Per-connection `(time, offset)` entries for the current chunk
+    chunk_index: BTreeMap<u32, Vec<(Time, u32)>>,
+    chunk_start_time: Time,
+    chunk_end_time: Time,
+}
+
+impl BagWriter<BufWriter<File>> {
+    /// Create a new bag file at the provided path, truncating it if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> BagWriter<W> {
+    /// Start writing a bag into the provided writer.
+    ///
+    /// The bag begins at the current position of the writer.
+    pub fn new(mut writer: W) -> Result<Self> {
+        let start = writer.stream_position()?;
+        writer.write_all(VERSION_STRING.as_bytes())?;
+        write_bag_header(&mut writer, 0, 0, 0)?;
+        let pos = writer.stream_position()? - start;
+        Ok(Self {
+            writer: Some(writer),
+            start,
+            pos,
+            compression: Compression::None,
+            chunk_threshold: DEFAULT_CHUNK_THRESHOLD,
+            max_chunk_len: u32::MAX as usize,
+            connections: BTreeMap::new(),
+            chunks: Vec::new(),
+            chunk_data: Vec::new(),
+            chunk_index: BTreeMap::new(),
+            chunk_start_time: Time::MAX,
+            chunk_end_time: Time::ZERO,
+        })
+    }
+
+    /// Set compression used for chunks written after this call.
+    ///
+    /// By default chunks are written without compression.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set size of uncompressed chunk data in bytes after which the chunk gets
+    /// written into the file.
+    ///
+    /// Bigger chunks compress better, while smaller ones are faster to
+    /// access randomly. The default value is 768 KiB, same as for
+    /// `rosbag record --chunksize`. Size of chunk data is stored as a 32-bit
+    /// integer, so chunks get written before exceeding 4 GiB regardless of
+    /// the threshold.
+    pub fn set_chunk_threshold(&mut self, threshold: usize) {
+        self.chunk_threshold = threshold;
+    }
+
+    /// Register a new connection.
+    ///
+    /// The connection `id` must be unique and will be used by
+    /// [`BagWriter::write_message`] to associate messages with this
+    /// connection.
+    pub fn add_connection(&mut self, conn: &Connection<'_>) -> Result<()> {
+        if self.connections.contains_key(&conn.id) {
+            let err = Error::new(ErrorKind::DuplicateConnection(conn.id));
+            return Err(err.in_field("conn").in_record("Connection"));
+        }
+        let entry = ConnectionEntry {
+            record: encode_connection(conn),
+            in_chunk: false,
+        };
+        self.connections.insert(conn.id, entry);
+        Ok(())
+    }
+
+    /// Write message on a previously registered connection.
+    ///
+    /// Returns error if the message together with its connection record
+    /// does not fit into a chunk of 4 GiB.
+    pub fn write_message(&mut self, msg: &MessageData<'_>) -> Result<()> {
+        let conn = self.connections.get(&msg.conn_id).ok_or_else(|| {
+            let err = Error::new(ErrorKind::UnknownConnection(msg.conn_id));
+            err.in_field("conn").in_record("MessageData")
+        })?;
+        let mut header = Vec::new();
+        push_field(&mut header, "op", &[MessageData::OP]);
+        push_field(&mut header, "conn", &msg.conn_id.to_le_bytes());
+        push_field(&mut header, "time", &time_to_bytes(msg.time));
+
+        // the connection record gets repeated in a new chunk
+        let len = conn.record.len() + 8 + header.len() + msg.data.len();
+        if len > self.max_chunk_len {
+            let err = Error::new(ErrorKind::RecordTooLarge);
+            return Err(err.in_field("data").in_record("MessageData"));
+        }
+        if self.chunk_data.len() + len > self.max_chunk_len {
+            self.flush_chunk()?;
+        }
+
+        let conn = self
+            .connections
+            .get_mut(&msg.conn_id)
+            .expect("connection is present");
+        if !conn.in_chunk {
+            self.chunk_data.extend_from_slice(&conn.record);
+            conn.in_chunk = true;
+        }
+        let offset = u32::try_from(self.chunk_data.len()).expect("chunk size is checked");
+        push_record(&mut self.chunk_data, &header, msg.data);
+
+        self.chunk_index
+            .entry(msg.conn_id)
+            .or_default()
+            .push((msg.time, offset));
+        self.chunk_start_time = self.chunk_start_time.min(msg.time);
+        self.chunk_end_time = self.chunk_end_time.max(msg.time);
+
+        if self.chunk_data.len() >= self.chunk_threshold {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Write the current chunk and its `IndexData` records into the file.
+    pub(crate) fn flush_chunk(&mut self) -> Result<()> {
+        if self.chunk_index.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+
+        let compression = self.compression;
+        let mut header = Vec::new();
+        push_field(&mut header, "op", &[Chunk::OP]);
+        push_field(&mut header, "compression", compression.as_str().as_bytes());
+        let size = u32::try_from(self.chunk_data.len()).expect("chunk size is checked");
+        push_field(&mut header, "size", &size.to_le_bytes());
+        let data = compression.compress(&self.chunk_data)?;
+        if u32::try_from(data.len()).is_err() {
+            let err = Error::new(ErrorKind::RecordTooLarge);
+            return Err(err.in_field("data").in_record("Chunk"));
+        }
+        push_record(&mut buf, &header, &data);
+
+        let index = std::mem::take(&mut self.chunk_index);
+        let (start_time, end_time) = (self.chunk_start_time, self.chunk_end_time);
+        self.write_chunk_record(buf, start_time, end_time, &index)?;
+
+        self.chunk_data.clear();
+        self.chunk_start_time = Time::MAX;
+        self.chunk_end_time = Time::ZERO;
+        for conn in self.connections.values_mut() {
+            conn.in_chunk = false;
+        }
+        Ok(())
+    }
+
+    /// Write already encoded `Chunk` record as-is followed by `IndexData`
+    /// records built from the per-connection `(time, offset)` entries.
+    ///
+    /// Connection records referenced by the chunk must be already
+    /// registered using [`BagWriter::add_connection`].
+    pub(crate) fn write_raw_chunk(
+        &mut self,
+        record: &[u8],
+        start_time: Time,
+        end_time: Time,
+        index: &BTreeMap<u32, Vec<(Time, u32)>>,
+    ) -> Result<()> {
+        self.flush_chunk()?;
+        self.write_chunk_record(record.to_vec(), start_time, end_time, index)
+    }
+
+    fn write_chunk_record(
+        &mut self,
+        mut buf: Vec<u8>,
+        start_time: Time,
+        end_time: Time,
+        index: &BTreeMap<u32, Vec<(Time, u32)>>,
+    ) -> Result<()> {
+        let mut counts = BTreeMap::new();
+        for (&conn_id, entries) in index.iter() {
+            encode_index_data(&mut buf, conn_id, entries);
+            counts.insert(conn_id, entries.len() as u32);
+        }
+        self.chunks.push(ChunkEntry {
+            pos: self.pos,
+            start_time,
+            end_time,
+            counts,
+        });
+        Ok(self.write_all(&buf)?)
+    }
+
+    fn inner(&mut self) -> &mut W {
+        self.writer
+            .as_mut()
+            .expect("writer is present until finished")
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner().write_all(buf)?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    fn finish_inner(&mut self) -> Result<()> {
+        self.flush_chunk()?;
+        let index_pos = self.pos;
+
+        let mut buf = Vec::new();
+        for conn in self.connections.values() {
+            buf.extend_from_slice(&conn.record);
+        }
+        for chunk in self.chunks.iter() {
+            encode_chunk_info(
+                &mut buf,
+                chunk.pos,
+                chunk.start_time,
+                chunk.end_time,
+                &chunk.counts,
+            );
+        }
+        self.write_all(&buf)?;
+
+        let conn_count = self.connections.len() as u32;
+        let chunk_count = self.chunks.len() as u32;
+        let (start, end) = (self.start, self.start + self.pos);
+        let writer = self.inner();
+        writer.seek(SeekFrom::Start(start + VERSION_STRING.len() as u64))?;
+        write_bag_header(writer, index_pos, conn_count, chunk_count)?;
+        writer.seek(SeekFrom::Start(end))?;
+        Ok(writer.flush()?)
+    }
+
+    /// Write remaining messages and the index section, update the bag header
+    /// and return the underlying writer.
+    ///
+    /// If the writer gets dropped without calling this method, the bag will
+    /// be finished on a best-effort basis with errors being ignored.
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_inner()?;
+        Ok(self
+            .writer
+            .take()
+            .expect("writer is present until finished"))
+    }
+}
+
+impl<W: Write + Seek> Drop for BagWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finish_inner();
+        }
+    }
+}
+
+fn write_bag_header<W: Write>(
+    w: &mut W,
+    index_pos: u64,
+    conn_count: u32,
+    chunk_count: u32,
+) -> io::Result<()> {
+    let mut header = Vec::new();
+    push_field(&mut header, "op", &[ROSBAG_HEADER_OP]);
+    push_field(&mut header, "index_pos", &index_pos.to_le_bytes());
+    push_field(&mut header, "conn_count", &conn_count.to_le_bytes());
+    push_field(&mut header, "chunk_count", &chunk_count.to_le_bytes());
+    let padding = vec![b' '; BAG_HEADER_LEN - header.len()];
+    let mut buf = Vec::new();
+    push_record(&mut buf, &header, &padding);
+    w.write_all(&buf)
+}
+
+/// Append `IndexData` record with `(time, offset)` entries of a connection.
+pub(crate) fn encode_index_data(buf: &mut Vec<u8>, conn_id: u32, entries: &[(Time, u32)]) {
+    let mut header = Vec::new();
+    push_field(&mut header, "op", &[IndexData::OP]);
+    push_field(&mut header, "ver", &1u32.to_le_bytes());
+    push_field(&mut header, "conn", &conn_id.to_le_bytes());
+    push_field(&mut header, "count", &(entries.len() as u32).to_le_bytes());
+    let mut data = Vec::with_capacity(12 * entries.len());
+    for &(time, offset) in entries {
+        data.extend_from_slice(&time_to_bytes(time));
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    push_record(buf, &header, &data);
+}
+
+/// Append `ChunkInfo` record with per-connection message counts.
+pub(crate) fn encode_chunk_info(
+    buf: &mut Vec<u8>,
+    chunk_pos: u64,
+    start_time: Time,
+    end_time: Time,
+    counts: &BTreeMap<u32, u32>,
+) {
+    let mut header = Vec::new();
+    push_field(&mut header, "op", &[ChunkInfo::OP]);
+    push_field(&mut header, "ver", &1u32.to_le_bytes());
+    push_field(&mut header, "chunk_pos", &chunk_pos.to_le_bytes());
+    push_field(&mut header, "start_time", &time_to_bytes(start_time));
+    push_field(&mut header, "end_time", &time_to_bytes(end_time));
+    push_field(&mut header, "count", &(counts.len() as u32).to_le_bytes());
+    let mut data = Vec::with_capacity(8 * counts.len());
+    for (conn_id, count) in counts.iter() {
+        data.extend_from_slice(&conn_id.to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+    }
+    push_record(buf, &header, &data);
+}
+
+pub(crate) fn encode_connection(conn: &Connection<'_>) -> Vec<u8> {
+    let mut header = Vec::new();
+    push_field(&mut header, "op", &[Connection::OP]);
+    push_field(&mut header, "conn", &conn.id.to_le_bytes());
+    push_field(&mut header, "topic", conn.storage_topic.as_bytes());
+
+    let mut md5sum = [0u8; 32];
+    let md5sum =
+        base16ct::lower::encode(&conn.md5sum, &mut md5sum).expect("buffer has correct length");
+    let mut data = Vec::new();
+    push_field(&mut data, "topic", conn.topic.as_bytes());
+    push_field(&mut data, "type", conn.tp.as_bytes());
+    push_field(&mut data, "md5sum", md5sum);
+    push_field(
+        &mut data,
+        "message_definition",
+        conn.message_definition.as_bytes(),
+    );
+    if !conn.caller_id.is_empty() {
+        push_field(&mut data, "callerid", conn.caller_id.as_bytes());
+    }
+    if conn.latching {
+        push_field(&mut data, "latching", b"1");
+    }
+
+    let mut buf = Vec::new();
+    push_record(&mut buf, &header, &data);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BagWriter;
+    use crate::record_types::{Compression, MessageData};
+    use crate::test_utils::{connection, message_time, string_msg, write_bag, TOPICS};
+    use crate::time::Time;
+    use crate::{ChunkRecord, ErrorKind, RosBag};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        for &compression in &[Compression::None, Compression::Bzip2, Compression::Lz4] {
+            let bag = RosBag::from_bytes(write_bag(compression, 5, 3)).unwrap();
+            assert_eq!(bag.get_conn_count(), 2);
+            assert_eq!(bag.get_chunk_count(), 4);
+            let report = bag.verify();
+            assert!(report.is_ok(), "{:?}: {}", compression, report);
+            assert_eq!(report.message_count, 10);
+
+            for record in bag.chunk_records() {
+                if let ChunkRecord::Chunk(chunk) = record.unwrap() {
+                    assert_eq!(chunk.compression, compression);
+                }
+            }
+            for (id, topic) in TOPICS.iter().enumerate() {
+                let conn = bag.connection(id as u32).unwrap();
+                assert_eq!(conn.topic, *topic);
+                assert_eq!(conn.md5sum, connection(id as u32).md5sum);
+                assert_eq!(bag.message_count(id as u32), 5);
+            }
+
+            let mut expected = Vec::new();
+            for i in 0..5 {
+                for (id, topic) in TOPICS.iter().enumerate() {
+                    let data = string_msg(&format!("{} {}", topic, i));
+                    expected.push((message_time(id as u32, i), data));
+                }
+            }
+            let messages = bag.messages().map(|msg| {
+                let msg = msg.unwrap();
+                (msg.time, msg.data().to_vec())
+            });
+            assert_eq!(messages.collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn connection_errors() {
+        let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_connection(&connection(0)).unwrap();
+        let err = writer.add_connection(&connection(0)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::DuplicateConnection(0));
+        assert_eq!(
+            (err.record(), err.field()),
+            (Some("Connection"), Some("conn"))
+        );
+
+        let msg = MessageData {
+            conn_id: 1,
+            time: Time::new(1, 0),
+            data: &string_msg("hello"),
+        };
+        let err = writer.write_message(&msg).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnknownConnection(1));
+        assert_eq!(
+            (err.record(), err.field()),
+            (Some("MessageData"), Some("conn"))
+        );
+    }
+
+    #[test]
+    fn chunk_size_limit() {
+        let mut writer = BagWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_chunk_threshold(usize::MAX);
+        writer.max_chunk_len = 300;
+        writer.add_connection(&connection(0)).unwrap();
+        let data = string_msg("hello");
+        let msg = MessageData {
+            conn_id: 0,
+            time: Time::new(1, 0),
+            data: &data,
+        };
+        for _ in 0..10 {
+            writer.write_message(&msg).unwrap();
+        }
+        let data = vec![0; 300];
+        let big = MessageData { data: &data, ..msg };
+        let err = writer.write_message(&big).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::RecordTooLarge);
+        assert_eq!(err.field(), Some("data"));
+
+        let bag = RosBag::from_bytes(writer.finish().unwrap().into_inner()).unwrap();
+        let mut chunks = 0;
+        for record in bag.chunk_records() {
+            if let ChunkRecord::Chunk(chunk) = record.unwrap() {
+                assert!(chunk.data.len() <= 300);
+                chunks += 1;
+            }
+        }
+        assert!(chunks > 1);
+        assert_eq!(bag.messages().count(), 10);
+    }
+}